@@ -0,0 +1,110 @@
+//! Command line interface.
+//!
+//! Without a subcommand the editor is opened on the given file, `./structure.ron` by default. `evolve` runs an
+//! evolution headlessly and `lint` lists the issues of a structure file:
+//!
+//! ```text
+//! evol [FILE]
+//! evol evolve [FILE] [--population N] [--generations N] [--seconds S] [--seed N] [--out FILE]
+//! evol lint [FILE]
+//! ```
+
+use crate::{editor::file::{read_structure, DEFAULT_FILE}, evolution::{Evolution, EvolutionConfig}, format::{to_ron, StructureFile}};
+
+pub enum Command {
+    /// Opens the editor on the given file.
+    Editor(String),
+    Evolve(EvolveArgs),
+    /// Checks the given file for invalid structures.
+    Lint(String),
+}
+
+pub struct EvolveArgs {
+    /// Structure to start the evolution from.
+    pub input: String,
+    /// File the best structure is written to.
+    pub output: String,
+    pub config: EvolutionConfig,
+}
+
+/// Parses the command line arguments, excluding the program name.
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Ok(Command::Editor(DEFAULT_FILE.to_string()));
+    };
+    match command.as_str() {
+        "evolve" => parse_evolve(rest).map(Command::Evolve),
+        "lint" => match rest {
+            [] => Ok(Command::Lint(DEFAULT_FILE.to_string())),
+            [file] if !file.starts_with("--") => Ok(Command::Lint(file.clone())),
+            [arg, ..] if arg.starts_with("--") => Err(format!("Unknown option {}", arg)),
+            _ => Err(format!("Unexpected argument {:?}", rest[1])),
+        },
+        _ if command.starts_with("--") => Err(format!("Unknown option {}", command)),
+        _ if rest.is_empty() => Ok(Command::Editor(command.clone())),
+        _ => Err(format!("Unexpected argument {:?}", rest[0])),
+    }
+}
+
+fn parse_evolve(args: &[String]) -> Result<EvolveArgs, String> {
+    let mut evolve = EvolveArgs {
+        input: DEFAULT_FILE.to_string(),
+        output: "./evolved.ron".to_string(),
+        config: EvolutionConfig::default(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            evolve.input = arg.clone();
+            continue;
+        }
+        let Some(value) = args.next() else {
+            return Err(format!("Missing value for {}", arg));
+        };
+        match arg.as_str() {
+            "--population" => evolve.config.population = parse_value(arg, value)?,
+            "--generations" => evolve.config.generations = parse_value(arg, value)?,
+            "--seconds" => evolve.config.sim_seconds = parse_value(arg, value)?,
+            "--seed" => evolve.config.seed = parse_value(arg, value)?,
+            "--out" => evolve.output = value.clone(),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+    Ok(evolve)
+}
+
+fn parse_value<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value {:?} for {}", value, arg))
+}
+
+/// Runs an evolution from the input file and writes the best structure to the output file.
+pub fn evolve(args: EvolveArgs) -> Result<(), String> {
+    let data = std::fs::read(&args.input).map_err(|e| format!("Failed to read {}: {}", args.input, e))?;
+    let StructureFile { mut metadata, structure: seed, .. } = StructureFile::from_ron(&data)
+        .map_err(|e| format!("Failed to parse {}: {}", args.input, e))?;
+
+    let mut evolution = Evolution::new(&seed, args.config);
+    evolution.run(|report| println!("{}", report));
+
+    let best = evolution.best().ok_or("No generations were evaluated")?;
+    metadata.touch();
+    std::fs::write(&args.output, to_ron(&best.structure, &metadata).map_err(|e| e.to_string())?)
+        .map_err(|e| format!("Failed to write {}: {}", args.output, e))?;
+    println!("Saved best structure ({:.3}) to {}", best.fitness.unwrap_or_default(), args.output);
+    Ok(())
+}
+
+/// Prints every issue of the structure in the given file. Fails if the file can't be read or has any issues.
+pub fn lint(file: String) -> Result<(), String> {
+    let structure = read_structure(file.as_ref()).map_err(|e| e.to_string())?.structure;
+    let issues = structure.validate();
+    for issue in issues.iter() {
+        println!("{}: {}", file, issue);
+    }
+    if !issues.is_empty() {
+        return Err(format!("{} has {} issue(s)", file, issues.len()));
+    }
+    println!("{} has no issues", file);
+    Ok(())
+}
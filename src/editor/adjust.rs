@@ -1,7 +1,17 @@
 use bevy::{prelude::*, input::mouse::MouseMotion, window::PrimaryWindow};
 
-use super::controls::{ActionEvent, CursorControlEvent, EditMode};
-use crate::{structure::*, util::*};
+use super::{
+    controls::{ActionEvent, AdjustStart, CursorControlEvent, EditMode, GroupTransform, Pivot, TypedValue},
+    snap::Snapping,
+    UnitAxis,
+};
+use crate::{selection::EntitySelected, structure::*, util::*};
+
+/// Cursor position and joint positions when a group adjustment started.
+pub struct GroupStart {
+    cursor: Vec2,
+    joints: Vec<(Entity, Vec3)>,
+}
 
 /// System to handle the movement of the joints when in adjust modes.
 pub fn adjust_control(
@@ -11,9 +21,23 @@ pub fn adjust_control(
     mut motion_evr: EventReader<MouseMotion>,
     window_q: Query<&Window, With<PrimaryWindow>>,
     cam_query: Query<(&Camera, &GlobalTransform)>,
+    entity_selected: Res<EntitySelected>,
+    pivot: Res<Pivot>,
+    adjust_start: Res<AdjustStart>,
+    typed: Res<TypedValue>,
+    snapping: Res<Snapping>,
+    mut group_start: Local<Option<GroupStart>>,
+    // unsnapped position of the joint for adjustments following the mouse motion
+    mut raw_pos: Local<Option<(EditMode, Vec3)>>,
     joint_query: Query<&Joint>,
     mut transform_query: Query<&mut Transform>,
 ) {
+    if !matches!(*edit_mode, EditMode::AdjustGroup(..)) {
+        *group_start = None;
+    }
+    if !matches!(*raw_pos, Some((mode, _)) if mode == *edit_mode) {
+        *raw_pos = None;
+    }
     if matches!(*edit_mode, EditMode::Default) {
         return;
     }
@@ -26,6 +50,17 @@ pub fn adjust_control(
         mouse_move += ev.delta;
     }
 
+    if let Some(value) = typed.value() {
+        let position = typed_position(
+            *edit_mode, value, &structure, &adjust_start, &joint_query, &transform_query.to_readonly()
+        );
+        if let Some((joint, pos)) = position {
+            transform_query.get_mut(joint).unwrap().translation = pos;
+            *raw_pos = None;
+            return;
+        }
+    }
+
     match *edit_mode {
         EditMode::AdjustExtend(joint) => {
             let Ok(point) = joint_query.get(joint) else {
@@ -40,6 +75,8 @@ pub fn adjust_control(
             }
 
             let parent = structure.node_parent_entity(point.node_index).unwrap();
+            let min_len = structure.node_parent(point.node_index)
+                .map_or(0.0, |p| structure[p].radius + structure[point.node_index].radius);
 
             let p_translation = transform_query.get(parent).unwrap().translation;
             let mut j_transform = transform_query.get_mut(joint).unwrap();
@@ -54,16 +91,18 @@ pub fn adjust_control(
             let dir_vec = j_pos.unwrap()-p_pos.unwrap();
             let mv = mouse_move.dot(dir_vec.normalize());
 
-            let relative_pos = j_transform.translation-p_translation;
+            let raw = raw_pos.map_or(j_transform.translation, |(_, pos)| pos);
+            let relative_pos = raw-p_translation;
 
             let mut pos = relative_pos + ((mv * 0.01) * relative_pos.normalize());
 
             // prevents extension to negative
-            if relative_pos.dot(pos - (2.0 * JOINT_RADIUS) * relative_pos.normalize()) < 0.0  {
-                pos = (2.0 * JOINT_RADIUS) * relative_pos.normalize();
+            if relative_pos.dot(pos - min_len * relative_pos.normalize()) < 0.0  {
+                pos = min_len * relative_pos.normalize();
             }
 
-            j_transform.translation = pos + p_translation;
+            *raw_pos = Some((*edit_mode, pos + p_translation));
+            j_transform.translation = p_translation + pos.normalize() * snapping.length(pos.length());
 
             commands.send_event(CursorControlEvent::Position(j_pos.unwrap()));
         },
@@ -123,10 +162,22 @@ pub fn adjust_control(
             let mouse_pos = ray.origin + ray_pos;
 
             if matches!(*edit_mode, EditMode::AdjustGrab(_)) {
-                j_transform.translation = mouse_pos;
+                j_transform.translation = snapping.position(mouse_pos);
             } else {
                 let dir_vec = (mouse_pos-p_translation).normalize();
-                j_transform.translation = p_translation + (radius * dir_vec);
+                let mut pos = radius * dir_vec;
+
+                // snaps the angle turned from where the joint started
+                if let Some(start) = adjust_start.get(joint).filter(|_| snapping.is_active()) {
+                    let from = start - p_translation;
+                    let rot_axis = from.cross(pos);
+                    if rot_axis != Vec3::ZERO {
+                        let angle = snapping.angle(from.angle_between(pos));
+                        pos = Quat::from_axis_angle(rot_axis.normalize(), angle) * from;
+                    }
+                }
+
+                j_transform.translation = p_translation + pos;
             }
         },
         EditMode::AdjustAxis(joint, axis) => {
@@ -142,6 +193,7 @@ pub fn adjust_control(
             }
 
             let j_transform = transform_query.get(joint).unwrap().translation;
+            let raw = raw_pos.map_or(j_transform, |(_, pos)| pos);
 
             let p_pos = cam.world_to_viewport(cam_transform, j_transform + axis.to_vec());
             let j_pos = cam.world_to_viewport(cam_transform, j_transform);
@@ -156,7 +208,12 @@ pub fn adjust_control(
 
             let mut transform = transform_query.get_mut(joint).unwrap();
 
-            transform.translation = transform.translation + (mv * 0.02) * axis.to_vec();
+            let raw = raw + (mv * 0.02) * axis.to_vec();
+            *raw_pos = Some((*edit_mode, raw));
+
+            // only the coordinate along the axis is snapped to the grid
+            let snapped = snapping.position(raw);
+            transform.translation = raw + axis.to_vec() * (snapped - raw).dot(axis.to_vec());
 
             commands.send_event(CursorControlEvent::Position(j_pos.unwrap()));
         },
@@ -187,7 +244,8 @@ pub fn adjust_control(
             let p = transform_query.get(parent).unwrap().translation;
             
             let mut j_transform = transform_query.get_mut(joint).unwrap();
-            let j = j_transform.translation;
+            // rotated from where the joint started so the angle can be snapped
+            let j = adjust_start.get(joint).unwrap_or(j_transform.translation);
 
             let relative_pos = j-p;
             
@@ -200,10 +258,161 @@ pub fn adjust_control(
             let dir_vec = intersection - center;
 
 
-            let rot = get_axis_rotation(j-center, dir_vec, axis.to_vec());
+            let angle = snapping.angle(get_rotation_angle(j-center, dir_vec, axis.to_vec()));
+            let rot = Quat::from_axis_angle(axis.to_vec(), angle);
 
             j_transform.translation = p + rot * relative_pos;
         },
+        EditMode::AdjustGroup(active, transform, axis) => {
+            let Ok(window) = window_q.get_single() else {
+                panic!("Window not found");
+            };
+
+            let Some(mouse_pos) = window.cursor_position() else {
+                return
+            };
+
+            let start = group_start.get_or_insert_with(|| GroupStart {
+                cursor: mouse_pos,
+                joints: entity_selected.joints()
+                    .filter_map(|joint| transform_query.get(joint).ok().map(|t| (joint, t.translation)))
+                    .collect(),
+            });
+            if start.joints.is_empty() {
+                return;
+            }
+
+            let center = match *pivot {
+                Pivot::Active => start.joints.iter()
+                    .find(|(joint, _)| *joint == active)
+                    .map(|(_, pos)| *pos),
+                Pivot::Centroid => None,
+            }.unwrap_or_else(|| start.joints.iter().map(|(_, pos)| *pos).sum::<Vec3>() / start.joints.len() as f32);
+
+            let Some(positions) = group_positions(
+                start, center, transform, axis, typed.value(), &snapping, mouse_pos, cam, cam_transform
+            ) else {
+                return;
+            };
+
+            for (joint, pos) in positions {
+                if let Ok(mut transform) = transform_query.get_mut(joint) {
+                    transform.translation = pos;
+                }
+            }
+        },
         _ => (),
     }
-}
\ No newline at end of file
+}
+
+/// Returns where a joint is placed by a typed value: moved along the axis by the value, extended to be the value away
+/// from its parent, or rotated around its parent by the value in degrees.
+fn typed_position(
+    edit_mode: EditMode,
+    value: f32,
+    structure: &Structure,
+    adjust_start: &AdjustStart,
+    joint_query: &Query<&Joint>,
+    transform_query: &Query<&Transform>,
+) -> Option<(Entity, Vec3)> {
+    let joint = match edit_mode {
+        EditMode::AdjustAxis(e, _) | EditMode::AdjustExtend(e) | EditMode::AdjustRotateAxis(e, _) => e,
+        _ => return None,
+    };
+    let start = adjust_start.get(joint)?;
+    let node = joint_query.get(joint).ok()?.node_index;
+    let parent = structure.node_parent_entity(node)
+        .and_then(|parent| transform_query.get(parent).ok())
+        .map(|transform| transform.translation);
+    // extended joints are kept from overlapping their parent
+    let min_len = structure.node_parent(node).map_or(0.0, |p| structure[p].radius + structure[node].radius);
+
+    let pos = match edit_mode {
+        EditMode::AdjustAxis(_, axis) => start + axis.to_vec() * value,
+        EditMode::AdjustExtend(_) => {
+            let p = parent?;
+            p + (start - p).normalize_or_zero() * value.max(min_len)
+        },
+        EditMode::AdjustRotateAxis(_, axis) => {
+            let p = parent?;
+            p + Quat::from_axis_angle(axis.to_vec(), value.to_radians()) * (start - p)
+        },
+        _ => return None,
+    };
+    Some((joint, pos))
+}
+
+/// Returns where the joints of a group adjustment are moved to with the cursor at `mouse_pos`. Joints are grabbed on
+/// the plane facing the camera, rotated by the angle the cursor turned around `center` on screen, and scaled by how
+/// much further the cursor is from `center` than it started. `axis` limits the movement and scaling to the axis and
+/// rotates around it.
+///
+/// A typed `value` is used instead of the cursor: the distance moved along the axis, the angle in degrees or the
+/// scale factor. Grabbing only takes a value when limited to an axis. Otherwise, the offset moved is snapped to the
+/// grid and the angle to the angle increment.
+fn group_positions(
+    start: &GroupStart,
+    center: Vec3,
+    transform: GroupTransform,
+    axis: Option<UnitAxis>,
+    value: Option<f32>,
+    snapping: &Snapping,
+    mouse_pos: Vec2,
+    cam: &Camera,
+    cam_transform: &GlobalTransform,
+) -> Option<Vec<(Entity, Vec3)>> {
+    let forward = Vec3::from(cam_transform.forward());
+    let center_pos = cam.world_to_viewport(cam_transform, center).ok()?;
+
+    let positions = match transform {
+        GroupTransform::Grab => {
+            let from = get_intersect_plane_ray(center, forward, cam.viewport_to_world(cam_transform, start.cursor).ok()?);
+            let to = get_intersect_plane_ray(center, forward, cam.viewport_to_world(cam_transform, mouse_pos).ok()?);
+            let offset = match (axis, value) {
+                (Some(axis), Some(value)) => axis.to_vec() * value,
+                (Some(axis), None) => axis.to_vec() * snapping.position(to - from).dot(axis.to_vec()),
+                (None, _) => snapping.position(to - from),
+            };
+            start.joints.iter().map(|(joint, pos)| (*joint, *pos + offset)).collect()
+        },
+        GroupTransform::Rotate => {
+            let rot_axis = axis.map_or(forward, |axis| axis.to_vec());
+            let angle = match value {
+                Some(value) => value.to_radians(),
+                None => {
+                    let from = start.cursor - center_pos;
+                    let to = mouse_pos - center_pos;
+                    if from == Vec2::ZERO || to == Vec2::ZERO {
+                        return None;
+                    }
+                    // screen angles turn the other way when the axis points towards the camera
+                    let angle = if rot_axis.dot(forward) < 0.0 { -from.angle_to(to) } else { from.angle_to(to) };
+                    snapping.angle(angle)
+                },
+            };
+            let rot = Quat::from_axis_angle(rot_axis, angle);
+            start.joints.iter().map(|(joint, pos)| (*joint, center + rot * (*pos - center))).collect()
+        },
+        GroupTransform::Scale => {
+            let scale = match value {
+                Some(value) => value,
+                None => {
+                    let from = (start.cursor - center_pos).length();
+                    if from == 0.0 {
+                        return None;
+                    }
+                    (mouse_pos - center_pos).length() / from
+                },
+            };
+            start.joints.iter().map(|(joint, pos)| {
+                let relative_pos = *pos - center;
+                let scaled = match axis {
+                    Some(axis) => relative_pos + axis.to_vec() * relative_pos.dot(axis.to_vec()) * (scale - 1.0),
+                    None => relative_pos * scale,
+                };
+                (*joint, center + scaled)
+            }).collect()
+        },
+    };
+    Some(positions)
+}
@@ -0,0 +1,321 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{app::AppExit, prelude::*, window::WindowCloseRequested};
+
+use super::{
+    file::{read_structure, report_issues, CurrentFile, OpenEvent},
+    history::History,
+    save::SaveEvent,
+    ui::{StatusMessage, HOVERED_BUTTON, NORMAL_BUTTON},
+    RespawnEvent,
+};
+use crate::{format::{to_ron, Metadata}, structure::Structure};
+
+/// Seconds between autosaves while there are unsaved changes.
+pub const AUTOSAVE_INTERVAL: f32 = 60.0;
+
+/// Autosave newer than the file that was loaded, offered to be restored.
+#[derive(Resource, Default)]
+pub struct RestoreOffer(pub Option<PathBuf>);
+
+/// Whether the prompt asking to save before quitting, or before opening another file, is open.
+#[derive(Resource, Default)]
+pub struct QuitConfirm {
+    pub open: bool,
+    /// File opened instead of quitting once the changes are saved or discarded.
+    then_open: Option<PathBuf>,
+    /// Quits, or opens `then_open`, once saving succeeded.
+    saving: bool,
+}
+
+impl QuitConfirm {
+    /// Opens the prompt, asking before quitting or before opening the given file.
+    pub fn ask(&mut self, then_open: Option<PathBuf>) {
+        self.open = true;
+        self.then_open = then_open;
+    }
+}
+
+#[derive(Component)]
+pub struct RestorePanel;
+
+#[derive(Component)]
+pub struct QuitPanel;
+
+/// Text of the quit prompt, naming the file to be opened if any.
+#[derive(Component)]
+pub struct QuitMessage;
+
+#[derive(Component, Clone, Copy)]
+pub enum DialogButton {
+    Restore,
+    Discard,
+    /// Saves the changes, then quits or opens the file.
+    Save,
+    /// Quits or opens the file without saving.
+    DontSave,
+    Cancel,
+}
+
+/// File the autosave of `path` is written to, next to it.
+pub fn autosave_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".autosave");
+    PathBuf::from(name)
+}
+
+/// Returns the autosave of `path` if there is one that is newer than the file.
+pub fn newer_autosave(path: &Path) -> Option<PathBuf> {
+    let autosave = autosave_path(path);
+    let autosaved = std::fs::metadata(&autosave).and_then(|m| m.modified()).ok()?;
+    match std::fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(modified) if modified >= autosaved => None,
+        _ => Some(autosave),
+    }
+}
+
+/// Deletes the autosave of `path`, if any.
+pub fn remove_autosave(path: &Path) {
+    let autosave = autosave_path(path);
+    if let Err(e) = std::fs::remove_file(&autosave) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove {}: {}", autosave.display(), e);
+        }
+    }
+}
+
+/// System that writes the structure next to the current file every `AUTOSAVE_INTERVAL` seconds while there are
+/// unsaved changes.
+pub fn autosave(
+    time: Res<Time>,
+    structure: Res<Structure>,
+    metadata: Res<Metadata>,
+    history: Res<History>,
+    current_file: Res<CurrentFile>,
+    mut elapsed: Local<f32>,
+    mut autosaved: Local<usize>,
+) {
+    *elapsed += time.delta_secs();
+    if *elapsed < AUTOSAVE_INTERVAL {
+        return;
+    }
+    *elapsed = 0.0;
+    if !history.is_dirty() || history.changes() == *autosaved {
+        return;
+    }
+
+    let mut structure = structure.clone();
+    structure.sync_muscle_data();
+    let path = autosave_path(&current_file.0);
+    let result = to_ron(&structure, &metadata)
+        .map_err(|e| e.to_string())
+        .and_then(|data| std::fs::write(&path, data).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => {
+            *autosaved = history.changes();
+            info!(":: Autosaved to {}", path.display());
+        },
+        Err(e) => warn!("Failed to autosave to {}: {}", path.display(), e),
+    }
+}
+
+/// System to ask whether to save before quitting when the window is closed with unsaved changes.
+pub fn close_requested(
+    mut commands: Commands,
+    current_file: Res<CurrentFile>,
+    history: Res<History>,
+    offer: Res<RestoreOffer>,
+    mut quit: ResMut<QuitConfirm>,
+    mut ev_close: EventReader<WindowCloseRequested>,
+    mut ev_exit: EventWriter<AppExit>,
+) {
+    // saving failed if there are still changes, which is shown by the save
+    if quit.saving {
+        quit.saving = false;
+        quit.open = false;
+        if !history.is_dirty() {
+            match quit.then_open.take() {
+                Some(path) => commands.trigger(OpenEvent { path, discard_changes: false }),
+                None => { ev_exit.send(AppExit::Success); },
+            }
+        }
+    }
+
+    if ev_close.read().count() == 0 {
+        return;
+    }
+    if history.is_dirty() {
+        quit.ask(None);
+    } else {
+        // an autosave not yet restored or discarded is kept for the next launch
+        if offer.0.is_none() {
+            remove_autosave(&current_file.0);
+        }
+        ev_exit.send(AppExit::Success);
+    }
+}
+
+pub fn setup_dialogs(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let font_handle: Handle<Font> = asset_server.load("fonts\\FiraCode-Regular.ttf");
+    let font = TextFont {
+        font: font_handle,
+        font_size: 13.0,
+        ..default()
+    };
+
+    let dialogs = [
+        (
+            "An autosave newer than the file was found.",
+            [("Restore", DialogButton::Restore), ("Discard", DialogButton::Discard)].as_slice(),
+            false,
+        ),
+        (
+            "There are unsaved changes.",
+            [
+                ("Save", DialogButton::Save),
+                ("Don't save", DialogButton::DontSave),
+                ("Cancel", DialogButton::Cancel),
+            ].as_slice(),
+            true,
+        ),
+    ];
+    for (message, buttons, is_quit) in dialogs {
+        let mut panel = commands.spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(40.0),
+                left: Val::Percent(20.0),
+                right: Val::Percent(20.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(8.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                display: Display::None,
+                ..default()
+            },
+            BackgroundColor(NORMAL_BUTTON.with_alpha(0.95)),
+            GlobalZIndex(1),
+        ));
+        if is_quit {
+            panel.insert(QuitPanel);
+        } else {
+            panel.insert(RestorePanel);
+        }
+        panel.with_children(|parent| {
+            let mut text = parent.spawn((Text::new(message), font.clone(), TextColor(Color::WHITE)));
+            if is_quit {
+                text.insert(QuitMessage);
+            }
+            parent.spawn(Node {
+                column_gap: Val::Px(8.0),
+                ..default()
+            }).with_children(|row| {
+                for (label, button) in buttons {
+                    row.spawn((
+                        Button,
+                        Node {
+                            padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                            ..default()
+                        },
+                        BackgroundColor(HOVERED_BUTTON),
+                        *button,
+                    )).with_child((Text::new(*label), font.clone(), TextColor(Color::WHITE)));
+                }
+            });
+        });
+    }
+}
+
+/// System to show the restore offer and the quit prompt while they are open.
+pub fn update_dialogs(
+    offer: Res<RestoreOffer>,
+    quit: Res<QuitConfirm>,
+    mut restore_q: Query<&mut Node, (With<RestorePanel>, Without<QuitPanel>)>,
+    mut quit_q: Query<&mut Node, (With<QuitPanel>, Without<RestorePanel>)>,
+    mut message_q: Query<&mut Text, With<QuitMessage>>,
+) {
+    if offer.is_changed() {
+        for mut node in restore_q.iter_mut() {
+            node.display = if offer.0.is_some() { Display::Flex } else { Display::None };
+        }
+    }
+    if quit.is_changed() {
+        for mut node in quit_q.iter_mut() {
+            node.display = if quit.open { Display::Flex } else { Display::None };
+        }
+        let action = match &quit.then_open {
+            Some(path) => format!("opening {}", path.display()),
+            None => "quitting".to_string(),
+        };
+        for mut text in message_q.iter_mut() {
+            text.0 = format!("There are unsaved changes. Save them before {}?", action);
+        }
+    }
+}
+
+/// System to handle the buttons of the restore offer and the quit prompt.
+pub fn dialog_interact(
+    mut commands: Commands,
+    mut structure: ResMut<Structure>,
+    mut metadata: ResMut<Metadata>,
+    mut history: ResMut<History>,
+    mut offer: ResMut<RestoreOffer>,
+    mut quit: ResMut<QuitConfirm>,
+    mut message: ResMut<StatusMessage>,
+    current_file: Res<CurrentFile>,
+    mut ev_exit: EventWriter<AppExit>,
+    interaction_q: Query<(&Interaction, &DialogButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in interaction_q.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            DialogButton::Restore => {
+                let Some(path) = offer.0.take() else {
+                    continue;
+                };
+                match read_structure(&path) {
+                    Ok(restored) => {
+                        // recorded so the restore can be undone, which also marks the changes as unsaved
+                        history.record(&structure);
+                        *structure = restored.structure;
+                        *metadata = restored.metadata;
+                        report_issues(&structure, &path, &mut message);
+                        commands.trigger(RespawnEvent);
+                        info!(":: Restored {}", path.display());
+                    },
+                    Err(e) => {
+                        error!("{}", e);
+                        message.set(e.to_string());
+                    },
+                }
+            },
+            DialogButton::Discard => {
+                offer.0 = None;
+                remove_autosave(&current_file.0);
+            },
+            DialogButton::Save => {
+                quit.saving = true;
+                commands.trigger(SaveEvent);
+            },
+            DialogButton::DontSave => {
+                remove_autosave(&current_file.0);
+                match quit.then_open.take() {
+                    Some(path) => {
+                        quit.open = false;
+                        commands.trigger(OpenEvent { path, discard_changes: true });
+                    },
+                    None => { ev_exit.send(AppExit::Success); },
+                }
+            },
+            DialogButton::Cancel => {
+                quit.open = false;
+                quit.then_open = None;
+            },
+        }
+    }
+}
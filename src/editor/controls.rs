@@ -1,25 +1,86 @@
-use bevy::prelude::*;
+use bevy::{input::keyboard::{Key, KeyboardInput}, prelude::*};
 
-use super::{delete, save, UnitAxis};
-use crate::selection::{EntitySelected, SelectableEntity};
+use serde::{Deserialize, Serialize};
 
-#[derive(Event)]
+use super::{autosave, delete, evolve, file, history, outliner::Outliner, save, snap::Snapping, ui::{KeyHelp, StatusMessage}, UnitAxis};
+use crate::selection::{EntitySelected, InvertSelectionEvent, SelectAllEvent, SelectableEntity};
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ActionEvent {
     Confirm,
     Cancel,
     Delete,
     Save,
+    SaveAs,
+    Open,
+    Evolve,
+    Undo,
+    Redo,
+    SelectAll,
+    InvertSelection,
 
     JointAdd,
     JointLink,
     MuscleAdd,
+    Subdivide,
     AdjustGrab,
     AdjustExtend,
     AdjustRotate,
+    AdjustScale,
+    TogglePivot,
+    ToggleSnap,
+    ToggleOutliner,
+    ToggleHelp,
 
     AxisChange(UnitAxis),
 }
 
+impl ActionEvent {
+    /// Every action, in the order the key help lists them.
+    pub const ALL: [ActionEvent; 26] = [
+        ActionEvent::Confirm, ActionEvent::Cancel,
+        ActionEvent::JointAdd, ActionEvent::JointLink, ActionEvent::MuscleAdd, ActionEvent::Subdivide,
+        ActionEvent::AdjustGrab, ActionEvent::AdjustExtend, ActionEvent::AdjustRotate, ActionEvent::AdjustScale,
+        ActionEvent::TogglePivot,
+        ActionEvent::AxisChange(UnitAxis::X), ActionEvent::AxisChange(UnitAxis::Y), ActionEvent::AxisChange(UnitAxis::Z),
+        ActionEvent::Delete, ActionEvent::SelectAll, ActionEvent::InvertSelection, ActionEvent::Undo, ActionEvent::Redo,
+        ActionEvent::Save, ActionEvent::SaveAs, ActionEvent::Open, ActionEvent::Evolve,
+        ActionEvent::ToggleSnap, ActionEvent::ToggleOutliner, ActionEvent::ToggleHelp,
+    ];
+
+    /// Description shown in the key help.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActionEvent::Confirm => "Confirm",
+            ActionEvent::Cancel => "Cancel",
+            ActionEvent::Delete => "Delete selection",
+            ActionEvent::Save => "Save",
+            ActionEvent::SaveAs => "Save as",
+            ActionEvent::Open => "Open",
+            ActionEvent::Evolve => "Evolve",
+            ActionEvent::Undo => "Undo",
+            ActionEvent::Redo => "Redo",
+            ActionEvent::SelectAll => "Select all",
+            ActionEvent::InvertSelection => "Invert selection",
+            ActionEvent::JointAdd => "Add joint",
+            ActionEvent::JointLink => "Link joints",
+            ActionEvent::MuscleAdd => "Add muscle",
+            ActionEvent::Subdivide => "Subdivide connector",
+            ActionEvent::AdjustGrab => "Grab",
+            ActionEvent::AdjustExtend => "Extend",
+            ActionEvent::AdjustRotate => "Rotate",
+            ActionEvent::AdjustScale => "Scale selection",
+            ActionEvent::TogglePivot => "Toggle pivot",
+            ActionEvent::ToggleSnap => "Toggle snapping",
+            ActionEvent::ToggleOutliner => "Toggle outliner",
+            ActionEvent::ToggleHelp => "Toggle key help",
+            ActionEvent::AxisChange(UnitAxis::X) => "Lock to X axis",
+            ActionEvent::AxisChange(UnitAxis::Y) => "Lock to Y axis",
+            ActionEvent::AxisChange(UnitAxis::Z) => "Lock to Z axis",
+        }
+    }
+}
+
 #[derive(Event)]
 pub enum CursorControlEvent {
     Position(Vec2),
@@ -33,79 +94,470 @@ pub struct UndoEvent(Entity);
 #[derive(Event)]
 pub struct CacheEvent(Entity);
 
+/// Joint positions when the current adjustment started.
+#[derive(Resource, Default)]
+pub struct AdjustStart(pub Vec<(Entity, Vec3)>);
+
+impl AdjustStart {
+    pub fn get(&self, joint: Entity) -> Option<Vec3> {
+        self.0.iter().find(|(e, _)| *e == joint).map(|(_, pos)| *pos)
+    }
+}
+
+/// Value typed while adjusting, used instead of the mouse until it is cleared.
+#[derive(Resource, Default)]
+pub struct TypedValue(pub Option<String>);
+
+impl TypedValue {
+    pub fn value(&self) -> Option<f32> {
+        self.0.as_ref()?.parse().ok()
+    }
+}
+
 
-#[derive(Default, Debug, Clone, Copy, Resource)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Resource)]
 pub enum EditMode {
     #[default]
     Default,
     JointAdd(Entity),
     JointLink(Entity),
     MuscleAdd(Entity),
+    /// Inserting a joint along the connector.
+    Subdivide(Entity),
     AdjustGrab(Entity),
     AdjustExtend(Entity),
     AdjustAxis(Entity, UnitAxis),
     AdjustRotate(Entity),
     AdjustRotateAxis(Entity, UnitAxis),
+    /// Several selected joints moved together. The entity is the joint the adjustment was started from.
+    AdjustGroup(Entity, GroupTransform, Option<UnitAxis>),
+}
+
+/// Transform applied to a group of selected joints.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GroupTransform {
+    Grab,
+    Rotate,
+    Scale,
+}
+
+impl GroupTransform {
+    /// Transform started by the action.
+    pub fn from_action(action: ActionEvent) -> Option<Self> {
+        match action {
+            ActionEvent::AdjustGrab => Some(GroupTransform::Grab),
+            ActionEvent::AdjustRotate => Some(GroupTransform::Rotate),
+            ActionEvent::AdjustScale => Some(GroupTransform::Scale),
+            _ => None,
+        }
+    }
+}
+
+/// Point a group of joints is rotated and scaled around.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub enum Pivot {
+    /// Center of the selected joints.
+    #[default]
+    Centroid,
+    /// The active joint of the selection.
+    Active,
 }
 
-#[derive(Resource)]
+impl Pivot {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pivot::Centroid => "Centroid",
+            Pivot::Active => "Active joint",
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        *self = match self {
+            Pivot::Centroid => Pivot::Active,
+            Pivot::Active => Pivot::Centroid,
+        };
+    }
+}
+
+impl EditMode {
+    /// Whether a joint is being moved.
+    pub fn is_adjust(&self) -> bool {
+        matches!(
+            self,
+            EditMode::AdjustGrab(_) | EditMode::AdjustExtend(_) | EditMode::AdjustAxis(..)
+                | EditMode::AdjustRotate(_) | EditMode::AdjustRotateAxis(..) | EditMode::AdjustGroup(..)
+        )
+    }
+
+    /// Whether the adjustment can be given an exact value by typing it.
+    pub fn takes_typed_value(&self) -> bool {
+        matches!(
+            self,
+            EditMode::AdjustExtend(_) | EditMode::AdjustAxis(..) | EditMode::AdjustRotateAxis(..)
+                | EditMode::AdjustGroup(..)
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EditMode::Default => "Default",
+            EditMode::JointAdd(_) => "Add joint",
+            EditMode::JointLink(_) => "Link joints",
+            EditMode::MuscleAdd(_) => "Add muscle",
+            EditMode::Subdivide(_) => "Subdivide",
+            EditMode::AdjustGrab(_) | EditMode::AdjustAxis(..) => "Grab",
+            EditMode::AdjustExtend(_) => "Extend",
+            EditMode::AdjustRotate(_) | EditMode::AdjustRotateAxis(..) => "Rotate",
+            EditMode::AdjustGroup(_, GroupTransform::Grab, _) => "Grab selection",
+            EditMode::AdjustGroup(_, GroupTransform::Rotate, _) => "Rotate selection",
+            EditMode::AdjustGroup(_, GroupTransform::Scale, _) => "Scale selection",
+        }
+    }
+
+    /// Axis the adjustment is locked to.
+    pub fn axis(&self) -> Option<UnitAxis> {
+        match self {
+            EditMode::AdjustAxis(_, axis) | EditMode::AdjustRotateAxis(_, axis) => Some(*axis),
+            EditMode::AdjustGroup(_, _, axis) => *axis,
+            _ => None,
+        }
+    }
+
+    /// Joint or connector the mode acts on.
+    pub fn target(&self) -> Option<Entity> {
+        match self {
+            EditMode::Default => None,
+            EditMode::JointAdd(e) | EditMode::JointLink(e) | EditMode::MuscleAdd(e) | EditMode::Subdivide(e)
+                | EditMode::AdjustGrab(e) | EditMode::AdjustExtend(e) | EditMode::AdjustAxis(e, _) | EditMode::AdjustRotate(e)
+                | EditMode::AdjustRotateAxis(e, _) | EditMode::AdjustGroup(e, ..) => Some(*e),
+        }
+    }
+
+    /// What `action` does in this mode, given the selection. Empty if the mode ignores the action. `editor_control`
+    /// carries out the effects and the key help lists the actions that have any, so the two can't disagree.
+    pub fn effects(&self, action: ActionEvent, selection: &EntitySelected) -> Vec<Effect> {
+        let to_default = Effect::Mode(EditMode::Default);
+        let release = Effect::Cursor(bevy::window::CursorGrabMode::None);
+        match (*self, action) {
+            (_, ActionEvent::ToggleSnap | ActionEvent::ToggleOutliner | ActionEvent::ToggleHelp) => {
+                vec![Effect::Action(action)]
+            },
+            (EditMode::Default, _) => self.default_effects(action, selection),
+            (EditMode::JointAdd(e), ActionEvent::Confirm) if selection.contains(e) => vec![Effect::Apply],
+            (EditMode::JointLink(e), ActionEvent::Confirm) if selection.is_joint() && !selection.contains(e) => {
+                vec![Effect::Apply]
+            },
+            (EditMode::MuscleAdd(e), ActionEvent::Confirm) if selection.is_connector() && !selection.contains(e) => {
+                vec![Effect::Apply]
+            },
+            (EditMode::Subdivide(e), ActionEvent::Confirm) if selection.contains(e) => vec![Effect::Apply],
+            (EditMode::JointAdd(_), ActionEvent::JointAdd | ActionEvent::Confirm | ActionEvent::Cancel)
+                | (EditMode::JointLink(_) | EditMode::MuscleAdd(_), ActionEvent::Confirm | ActionEvent::Cancel)
+                | (EditMode::Subdivide(_), ActionEvent::Subdivide | ActionEvent::Confirm | ActionEvent::Cancel) => {
+                vec![to_default]
+            },
+            (EditMode::AdjustGrab(e), ActionEvent::AdjustGrab | ActionEvent::Cancel)
+                | (EditMode::AdjustRotate(e), ActionEvent::AdjustRotate | ActionEvent::Cancel)
+                | (EditMode::AdjustRotateAxis(e, _), ActionEvent::Cancel) => vec![Effect::Revert(e), to_default],
+            (EditMode::AdjustExtend(e), ActionEvent::AdjustExtend | ActionEvent::Cancel)
+                | (EditMode::AdjustAxis(e, _), ActionEvent::Cancel) => vec![release, Effect::Revert(e), to_default],
+            (EditMode::AdjustGrab(_) | EditMode::AdjustRotate(_) | EditMode::AdjustRotateAxis(..), ActionEvent::Confirm) => {
+                vec![to_default]
+            },
+            (EditMode::AdjustExtend(_) | EditMode::AdjustAxis(..), ActionEvent::Confirm) => vec![release, to_default],
+            (EditMode::AdjustGrab(e), ActionEvent::AxisChange(axis)) => {
+                vec![Effect::Revert(e), Effect::Mode(EditMode::AdjustAxis(e, axis))]
+            },
+            (EditMode::AdjustAxis(e, axis), ActionEvent::AxisChange(new_axis)) => {
+                let mode = if new_axis == axis { EditMode::AdjustGrab(e) } else { EditMode::AdjustAxis(e, new_axis) };
+                vec![Effect::Revert(e), Effect::Mode(mode)]
+            },
+            (EditMode::AdjustRotate(e), ActionEvent::AxisChange(axis)) => {
+                vec![Effect::Revert(e), Effect::Mode(EditMode::AdjustRotateAxis(e, axis))]
+            },
+            (EditMode::AdjustRotateAxis(e, axis), ActionEvent::AxisChange(new_axis)) => {
+                let mode = if new_axis == axis {
+                    EditMode::AdjustRotate(e)
+                } else {
+                    EditMode::AdjustRotateAxis(e, new_axis)
+                };
+                vec![Effect::Revert(e), Effect::Mode(mode)]
+            },
+            (EditMode::AdjustGroup(e, transform, _), _) if Some(transform) == GroupTransform::from_action(action) => {
+                vec![Effect::Revert(e), to_default]
+            },
+            (EditMode::AdjustGroup(e, _, _), ActionEvent::Cancel) => vec![Effect::Revert(e), to_default],
+            (EditMode::AdjustGroup(..), ActionEvent::Confirm) => vec![to_default],
+            (EditMode::AdjustGroup(..), ActionEvent::TogglePivot) => vec![Effect::Action(action)],
+            // joints are placed from where they started, so they don't need to be reverted
+            (EditMode::AdjustGroup(e, transform, axis), ActionEvent::AxisChange(new_axis)) => {
+                let axis = if Some(new_axis) == axis { None } else { Some(new_axis) };
+                vec![Effect::Mode(EditMode::AdjustGroup(e, transform, axis))]
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    fn default_effects(&self, action: ActionEvent, selection: &EntitySelected) -> Vec<Effect> {
+        // joints are moved together when more than one is selected
+        let group = (selection.joints().count() > 1)
+            .then(|| match selection.0 {
+                Some(SelectableEntity::Joint(joint)) => Some(joint),
+                _ => selection.joints().next(),
+            })
+            .flatten();
+        if let (Some(joint), Some(transform)) = (group, GroupTransform::from_action(action)) {
+            return vec![Effect::Cache(joint), Effect::Mode(EditMode::AdjustGroup(joint, transform, None))];
+        }
+
+        match (action, selection.0.as_ref()) {
+            (ActionEvent::Save | ActionEvent::SaveAs | ActionEvent::Open | ActionEvent::Evolve | ActionEvent::Undo
+                | ActionEvent::Redo | ActionEvent::SelectAll | ActionEvent::InvertSelection, _) => {
+                vec![Effect::Action(action)]
+            },
+            (ActionEvent::Delete, Some(_)) => vec![Effect::Action(action)],
+            (ActionEvent::TogglePivot, _) if group.is_some() => vec![Effect::Action(action)],
+            (ActionEvent::JointAdd, Some(&SelectableEntity::Joint(joint))) => {
+                vec![Effect::Mode(EditMode::JointAdd(joint))]
+            },
+            (ActionEvent::JointLink, Some(&SelectableEntity::Joint(joint))) => {
+                vec![Effect::Mode(EditMode::JointLink(joint))]
+            },
+            (ActionEvent::AdjustExtend, Some(&SelectableEntity::Joint(joint))) => vec![
+                Effect::Cursor(bevy::window::CursorGrabMode::Confined),
+                Effect::Cache(joint),
+                Effect::Mode(EditMode::AdjustExtend(joint)),
+            ],
+            (ActionEvent::AdjustGrab, Some(&SelectableEntity::Joint(joint))) => {
+                vec![Effect::Cache(joint), Effect::Mode(EditMode::AdjustGrab(joint))]
+            },
+            (ActionEvent::AdjustRotate, Some(&SelectableEntity::Joint(joint))) => {
+                vec![Effect::Cache(joint), Effect::Mode(EditMode::AdjustRotate(joint))]
+            },
+            (ActionEvent::MuscleAdd, Some(&SelectableEntity::Connector(connector))) => {
+                vec![Effect::Mode(EditMode::MuscleAdd(connector))]
+            },
+            (ActionEvent::Subdivide, Some(&SelectableEntity::Connector(connector))) => {
+                vec![Effect::Mode(EditMode::Subdivide(connector))]
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    /// Actions `editor_control` responds to in this mode, in the order the key help lists them.
+    pub fn accepted_actions(&self, selection: &EntitySelected) -> Vec<ActionEvent> {
+        ActionEvent::ALL.into_iter().filter(|action| !self.effects(*action, selection).is_empty()).collect()
+    }
+}
+
+/// Step of an action in `EditMode::effects`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// Switches to the mode.
+    Mode(EditMode),
+    /// Remembers where the joint, or the selected joints of a group, started.
+    Cache(Entity),
+    /// Moves the joints back to where they started.
+    Revert(Entity),
+    Cursor(bevy::window::CursorGrabMode),
+    /// Adds, links or subdivides as the current mode does.
+    Apply,
+    /// Actions doing the same in every mode that accepts them.
+    Action(ActionEvent),
+}
+
+/// File the key bindings are read from.
+const CONTROLS_FILE: &str = "./controls.ron";
+
+/// Key triggering an action, pressed along with the given modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Binding {
+    pub action: ActionEvent,
+    pub key: KeyCode,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+}
+
+impl Binding {
+    fn new(action: ActionEvent, key: KeyCode) -> Self {
+        Binding { action, key, ctrl: false, shift: false }
+    }
+
+    fn ctrl(action: ActionEvent, key: KeyCode) -> Self {
+        Binding { action, key, ctrl: true, shift: false }
+    }
+
+    /// Name of the key with its modifiers, such as `Ctrl+Shift+Z`.
+    pub fn key_name(&self) -> String {
+        let mut name = String::new();
+        if self.ctrl {
+            name.push_str("Ctrl+");
+        }
+        if self.shift {
+            name.push_str("Shift+");
+        }
+        name.push_str(&key_name(self.key));
+        name
+    }
+}
+
+/// Name of a key as shown to the user. Letter and digit keys are shown without their prefix.
+pub fn key_name(key: KeyCode) -> String {
+    let name = format!("{:?}", key);
+    name.strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+/// Key bindings of the editor. Read from `controls.ron` if there is one, the defaults are used otherwise. Actions the
+/// file doesn't bind, such as ones added after it was written, get their default key.
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
 pub struct EditorControls {
-    pub joint_add_key: Option<KeyCode>,
-    pub joint_link_key: Option<KeyCode>,
-    pub muscle_add_key: Option<KeyCode>,
-    pub adjust_grab_key: Option<KeyCode>,
-    pub adjust_rotate_key: Option<KeyCode>,
-    pub adjust_extend_key: Option<KeyCode>,
-    pub axis_x_key: Option<KeyCode>,
-    pub axis_y_key: Option<KeyCode>,
-    pub axis_z_key: Option<KeyCode>,
-    pub action_confirm_key: Option<MouseButton>,
-    pub action_cancel_key: Option<KeyCode>,
-    pub action_delete_key: Option<KeyCode>,
-    pub action_save_key: Option<KeyCode>,
+    pub bindings: Vec<Binding>,
+    pub confirm_button: Option<MouseButton>,
+    /// Inverts snapping while held.
+    pub snap_invert_key: Option<KeyCode>,
 }
 
 impl Default for EditorControls {
     fn default() -> Self {
         EditorControls {
-            joint_add_key: Some(KeyCode::Tab),
-            joint_link_key: Some(KeyCode::KeyL),
-            muscle_add_key: Some(KeyCode::KeyM),
-            adjust_grab_key: Some(KeyCode::KeyG),
-            adjust_rotate_key: Some(KeyCode::KeyR),
-            adjust_extend_key: Some(KeyCode::KeyE),
-            axis_x_key: Some(KeyCode::KeyX),
-            axis_y_key: Some(KeyCode::KeyY),
-            axis_z_key: Some(KeyCode::KeyZ),
-            action_confirm_key: Some(MouseButton::Left),
-            action_cancel_key: Some(KeyCode::Escape),
-            action_delete_key: Some(KeyCode::Delete),
-            action_save_key: Some(KeyCode::KeyS),
+            bindings: vec![
+                Binding::new(ActionEvent::JointAdd, KeyCode::Tab),
+                Binding::new(ActionEvent::JointLink, KeyCode::KeyL),
+                Binding::new(ActionEvent::MuscleAdd, KeyCode::KeyM),
+                Binding::new(ActionEvent::Subdivide, KeyCode::KeyD),
+                Binding::new(ActionEvent::AdjustGrab, KeyCode::KeyG),
+                Binding::new(ActionEvent::AdjustRotate, KeyCode::KeyR),
+                Binding::new(ActionEvent::AdjustExtend, KeyCode::KeyE),
+                // only available when more than one joint is selected
+                Binding::new(ActionEvent::AdjustScale, KeyCode::KeyT),
+                Binding::new(ActionEvent::TogglePivot, KeyCode::KeyP),
+                Binding::new(ActionEvent::ToggleSnap, KeyCode::KeyN),
+                Binding::new(ActionEvent::ToggleOutliner, KeyCode::KeyH),
+                Binding::new(ActionEvent::ToggleHelp, KeyCode::F1),
+                Binding::new(ActionEvent::AxisChange(UnitAxis::X), KeyCode::KeyX),
+                Binding::new(ActionEvent::AxisChange(UnitAxis::Y), KeyCode::KeyY),
+                Binding::new(ActionEvent::AxisChange(UnitAxis::Z), KeyCode::KeyZ),
+                Binding::new(ActionEvent::Cancel, KeyCode::Escape),
+                Binding::new(ActionEvent::Delete, KeyCode::Delete),
+                Binding::new(ActionEvent::Save, KeyCode::KeyS),
+                Binding::new(ActionEvent::SaveAs, KeyCode::KeyW),
+                Binding::new(ActionEvent::Open, KeyCode::KeyO),
+                Binding::new(ActionEvent::Evolve, KeyCode::KeyV),
+                Binding::ctrl(ActionEvent::Undo, KeyCode::KeyZ),
+                Binding { shift: true, ..Binding::ctrl(ActionEvent::Redo, KeyCode::KeyZ) },
+                Binding::new(ActionEvent::SelectAll, KeyCode::KeyA),
+                Binding::new(ActionEvent::InvertSelection, KeyCode::KeyI),
+            ],
+            confirm_button: Some(MouseButton::Left),
+            // shift is taken by shift-click selection and redo
+            snap_invert_key: Some(KeyCode::AltLeft),
         }
     }
 }
 
 impl EditorControls {
+    /// Reads the bindings from the controls file, adding the defaults of actions it doesn't bind. Uses the defaults if
+    /// there is no file.
+    pub fn load() -> Result<Self, String> {
+        match std::fs::read(CONTROLS_FILE) {
+            Ok(data) => {
+                let mut controls: EditorControls = ron::de::from_bytes(&data).map_err(|e| {
+                    format!("(Parse): {}:{}:{}: {}", CONTROLS_FILE, e.position.line, e.position.col, e.code)
+                })?;
+                controls.add_missing_defaults();
+                Ok(controls)
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!(":: No {}, using the default controls", CONTROLS_FILE);
+                Ok(EditorControls::default())
+            },
+            Err(e) => Err(format!("(Read): Failed to read {}: {}", CONTROLS_FILE, e)),
+        }
+    }
+
+    /// Adds the default binding of every action without any binding. A default whose key is already bound to another
+    /// action is left out, so the action stays unbound.
+    fn add_missing_defaults(&mut self) {
+        for default in EditorControls::default().bindings {
+            if self.bindings.iter().any(|b| b.action == default.action) {
+                continue;
+            }
+            let taken = self.bindings.iter()
+                .any(|b| b.key == default.key && b.ctrl == default.ctrl && b.shift == default.shift);
+            if taken {
+                warn!("{} is unbound, its default key {} is already bound", default.action.name(), default.key_name());
+                continue;
+            }
+            self.bindings.push(default);
+        }
+    }
+
+    /// Describes every key bound to more than one action, along with the snapping invert key and the confirm button
+    /// when they are used by something else too.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        for (i, binding) in self.bindings.iter().enumerate() {
+            let same_key = |other: &&Binding| {
+                other.key == binding.key && other.ctrl == binding.ctrl && other.shift == binding.shift
+            };
+            // each conflict is reported once, from the first of its bindings
+            if self.bindings[..i].iter().any(|other| same_key(&other)) {
+                continue;
+            }
+            let actions: Vec<&str> = self.bindings[i..].iter()
+                .filter(same_key)
+                .map(|other| other.action.name())
+                .collect();
+            if actions.len() > 1 {
+                conflicts.push(format!("{} is bound to {}", binding.key_name(), actions.join(", ")));
+            }
+        }
+
+        if let Some(key) = self.snap_invert_key {
+            let shift = matches!(key, KeyCode::ShiftLeft | KeyCode::ShiftRight);
+            let ctrl = matches!(key, KeyCode::ControlLeft | KeyCode::ControlRight);
+            let mut uses: Vec<&str> = self.bindings.iter()
+                .filter(|b| b.key == key || (shift && b.shift) || (ctrl && b.ctrl))
+                .map(|b| b.action.name())
+                .collect();
+            if shift {
+                uses.push("shift-click selection");
+            }
+            if !uses.is_empty() {
+                conflicts.push(format!("{} inverts snapping and is used by {}", key_name(key), uses.join(", ")));
+            }
+        }
+
+        if let Some(button) = self.confirm_button {
+            let camera = crate::camera::PanOrbitSettings::default();
+            if [camera.pan_key, camera.orbit_key].contains(&Some(button)) {
+                conflicts.push(format!("{:?} click confirms and moves the camera", button));
+            }
+        }
+        conflicts
+    }
+
+    /// Action of a key pressed with the given modifiers. Bindings without shift also apply while shift is held,
+    /// unless the key is bound with shift as well.
     pub fn key_to_action(
         &self,
-        key: KeyCode
+        key: KeyCode,
+        ctrl: bool,
+        shift: bool,
     ) -> Option<ActionEvent> {
-        match key {
-            _ if Some(key) == self.joint_add_key => Some(ActionEvent::JointAdd),
-            _ if Some(key) == self.joint_link_key => Some(ActionEvent::JointLink),
-            _ if Some(key) == self.muscle_add_key => Some(ActionEvent::MuscleAdd),
-            _ if Some(key) == self.adjust_grab_key => Some(ActionEvent::AdjustGrab),
-            _ if Some(key) == self.adjust_rotate_key => Some(ActionEvent::AdjustRotate),
-            _ if Some(key) == self.adjust_extend_key => Some(ActionEvent::AdjustExtend),
-            _ if Some(key) == self.axis_x_key => Some(ActionEvent::AxisChange(UnitAxis::X)),
-            _ if Some(key) == self.axis_y_key => Some(ActionEvent::AxisChange(UnitAxis::Y)),
-            _ if Some(key) == self.axis_z_key => Some(ActionEvent::AxisChange(UnitAxis::Z)),
-            // _ if Some(key) == self.action_confirm_key => Some(ActionEvent::Confirm),
-            _ if Some(key) == self.action_cancel_key => Some(ActionEvent::Cancel),
-            _ if Some(key) == self.action_delete_key => Some(ActionEvent::Delete),
-            _ if Some(key) == self.action_save_key => Some(ActionEvent::Save),
-            _ => None,
+        let mut matching = self.bindings.iter()
+            .filter(|b| b.key == key && b.ctrl == ctrl && (shift || !b.shift));
+        let first = matching.next()?;
+        if first.shift == shift {
+            return Some(first.action);
         }
+        matching.find(|b| b.shift == shift).or(Some(first)).map(|b| b.action)
     }
 
     pub fn mouse_to_action(
@@ -113,10 +565,45 @@ impl EditorControls {
         button: MouseButton
     ) -> Option<ActionEvent> {
         match button {
-            _ if Some(button) == self.action_confirm_key => Some(ActionEvent::Confirm),
+            _ if Some(button) == self.confirm_button => Some(ActionEvent::Confirm),
             _ => None,
         }
     }
+
+    /// Keys bound to an action, joined with `/`.
+    pub fn keys_for(&self, action: ActionEvent) -> Option<String> {
+        if action == ActionEvent::Confirm {
+            return self.confirm_button.map(|button| format!("{:?} click", button));
+        }
+        let keys: Vec<String> = self.bindings.iter()
+            .filter(|b| b.action == action)
+            .map(Binding::key_name)
+            .collect();
+        (!keys.is_empty()).then(|| keys.join("/"))
+    }
+}
+
+/// Replaces the default bindings with the ones in the controls file, and shows any error or conflicting bindings.
+pub fn load_controls(
+    mut controls: ResMut<EditorControls>,
+    mut message: ResMut<StatusMessage>,
+) {
+    match EditorControls::load() {
+        Ok(loaded) => *controls = loaded,
+        Err(e) => {
+            error!("{}", e);
+            message.set(format!("{}, using the default controls", e));
+            return;
+        },
+    }
+
+    let conflicts = controls.conflicts();
+    for conflict in conflicts.iter() {
+        warn!("Conflicting key binding: {}", conflict);
+    }
+    if !conflicts.is_empty() {
+        message.set(format!("Conflicting key bindings: {}", conflicts.join("; ")));
+    }
 }
 
 pub fn input_to_actions(
@@ -124,9 +611,25 @@ pub fn input_to_actions(
     key_input: Res<ButtonInput<KeyCode>>,
     mouse_input: Res<ButtonInput<MouseButton>>,
     controls: Res<EditorControls>,
+    prompt: Res<file::PathPrompt>,
+    quit: Res<autosave::QuitConfirm>,
+    mut snapping: ResMut<Snapping>,
 ) {
+    let inverted = controls.snap_invert_key.is_some_and(|key| key_input.pressed(key));
+    if snapping.inverted != inverted {
+        snapping.inverted = inverted;
+    }
+
+    // typing into the path prompt shouldn't trigger any actions, nor should editing behind the quit prompt
+    if prompt.is_open() || quit.open {
+        return;
+    }
+
+    let ctrl = key_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = key_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
     for key in key_input.get_just_pressed() {
-        if let Some(action) = controls.key_to_action(*key) {
+        if let Some(action) = controls.key_to_action(*key, ctrl, shift) {
             ev_action.send(action);
         }
     }
@@ -141,186 +644,68 @@ pub fn input_to_actions(
 pub fn editor_control(
     mut commands: Commands,
     mut edit_mode: ResMut<EditMode>,
+    mut prompt: ResMut<file::PathPrompt>,
+    mut history: ResMut<history::History>,
+    mut pivot: ResMut<Pivot>,
+    mut snapping: ResMut<Snapping>,
+    mut outliner: ResMut<Outliner>,
+    mut help: ResMut<KeyHelp>,
+    current_file: Res<file::CurrentFile>,
     entity_selected: Res<EntitySelected>,
     mut ev_action: EventReader<ActionEvent>,
     mut ev_cursor: EventReader<CursorControlEvent>,
     mut window_q: Query<&mut Window, With<bevy::window::PrimaryWindow>>,
 ) {
     for action in ev_action.read() {
-        match *edit_mode {
-            EditMode::Default => {
-                if let Some(SelectableEntity::Joint(joint)) = entity_selected.0 {
-                    match action {
-                        ActionEvent::JointAdd => {
-                            *edit_mode = EditMode::JointAdd(joint);
-                        },
-                        ActionEvent::JointLink => {
-                            *edit_mode = EditMode::JointLink(joint);
-                        }
-                        ActionEvent::AdjustExtend => {
-                            commands.send_event(CursorControlEvent::GrabMode(bevy::window::CursorGrabMode::Confined));
-
-                            commands.send_event(CacheEvent(joint));
-                            *edit_mode = EditMode::AdjustExtend(joint);
-                        },
-                        ActionEvent::AdjustGrab => {
-                            commands.send_event(CacheEvent(joint));
-                            *edit_mode = EditMode::AdjustGrab(joint);
-                        },
-                        ActionEvent::AdjustRotate => {
-                            commands.send_event(CacheEvent(joint));
-                            *edit_mode = EditMode::AdjustRotate(joint);
-                        },
-                        _ => (),
-                    }
-                } else if let Some(SelectableEntity::Connector(connector)) = entity_selected.0 {
-                    match action {
-                        ActionEvent::MuscleAdd => {
-                            *edit_mode = EditMode::MuscleAdd(connector);
-                        },
-                        _ => (),
-                    }
-                }
-            },
-            EditMode::JointAdd(e) => {
-                match action {
-                    ActionEvent::JointAdd | ActionEvent::Cancel => *edit_mode = EditMode::Default,
-                    ActionEvent::Confirm => {
-                        if entity_selected.contains(e) {
-                            commands.send_event(super::joint::JointAddEvent); 
-                        } else {
-                            *edit_mode = EditMode::Default;
-                        }
-                    },
-                    _ => (),
-                }
-            },
-            EditMode::JointLink(e) => {
-                match action {
-                    ActionEvent::Cancel => *edit_mode = EditMode::Default,
-                    ActionEvent::Confirm => {
-                        if entity_selected.is_joint() && !entity_selected.contains(e) {
-                            commands.send_event(super::joint::JointLinkEvent); 
-                        } else {
-                            *edit_mode = EditMode::Default;
-                        }
-                    },
-                    _ => (),
-                }
-            },
-            EditMode::MuscleAdd(e) => {
-                match action {
-                    ActionEvent::Cancel => *edit_mode = EditMode::Default,
-                    ActionEvent::Confirm => {
-                        if entity_selected.is_connector() && !entity_selected.contains(e) {
-                            commands.send_event(super::muscle::MuscleAddEvent); 
-                        } else {
-                            *edit_mode = EditMode::Default;
-                        }
-                    },
-                    _ => (),
-                }
-            },
-            EditMode::AdjustGrab(e) => {
-                match action {
-                    ActionEvent::AdjustGrab | ActionEvent::Cancel => {
-                        commands.send_event(UndoEvent(e));
-                        *edit_mode = EditMode::Default
-                    },
-                    ActionEvent::Confirm => {
-                        *edit_mode = EditMode::Default;
-                    },
-                    ActionEvent::AxisChange(axis) => {
-                        commands.send_event(UndoEvent(e));
-                        *edit_mode = EditMode::AdjustAxis(e, *axis);
-                    }
-                    _ => (),
-                }
-            },
-            EditMode::AdjustExtend(e) => {
-                match action {
-                    ActionEvent::AdjustExtend | ActionEvent::Cancel => {
-                        commands.send_event(CursorControlEvent::GrabMode(bevy::window::CursorGrabMode::None));
-
-                        commands.send_event(UndoEvent(e));
-                        *edit_mode = EditMode::Default
-                    },
-                    ActionEvent::Confirm => {
-                        commands.send_event(CursorControlEvent::GrabMode(bevy::window::CursorGrabMode::None));
+        let adjusting = edit_mode.is_adjust();
 
-                        *edit_mode = EditMode::Default;
-                    },
+        for effect in edit_mode.effects(*action, &entity_selected) {
+            match effect {
+                Effect::Mode(mode) => *edit_mode = mode,
+                Effect::Cache(joint) => { commands.send_event(CacheEvent(joint)); },
+                Effect::Revert(joint) => { commands.send_event(UndoEvent(joint)); },
+                Effect::Cursor(mode) => { commands.send_event(CursorControlEvent::GrabMode(mode)); },
+                Effect::Apply => match *edit_mode {
+                    EditMode::JointAdd(_) => { commands.send_event(super::joint::JointAddEvent); },
+                    EditMode::JointLink(_) => { commands.send_event(super::joint::JointLinkEvent); },
+                    EditMode::MuscleAdd(_) => { commands.send_event(super::muscle::MuscleAddEvent); },
+                    EditMode::Subdivide(_) => { commands.send_event(super::joint::SubdivideEvent); },
                     _ => (),
-                }
-            },
-            EditMode::AdjustAxis(e, axis) => {
-                match action {
-                    ActionEvent::AxisChange(new_axis) => {
-                        commands.send_event(UndoEvent(e));
-
-                        if *new_axis == axis {
-                            *edit_mode = EditMode::AdjustGrab(e);
-                        } else {
-                            *edit_mode = EditMode::AdjustAxis(e, *new_axis);
-                        }
-                    },
-                    ActionEvent::Cancel => {
-                        commands.send_event(CursorControlEvent::GrabMode(bevy::window::CursorGrabMode::None));
-
-                        commands.send_event(UndoEvent(e));
-                        *edit_mode = EditMode::Default;
-                    }
-                    ActionEvent::Confirm => {
-                        commands.send_event(CursorControlEvent::GrabMode(bevy::window::CursorGrabMode::None));
-
-                        *edit_mode = EditMode::Default;
-                    },
-                    _ => (),
-                }
-            },
-            EditMode::AdjustRotate(e) => {
-                match action {
-                    ActionEvent::AdjustRotate | ActionEvent::Cancel => {
-                        commands.send_event(UndoEvent(e));
-                        *edit_mode = EditMode::Default
-                    },
-                    ActionEvent::Confirm => {
-                        *edit_mode = EditMode::Default;
+                },
+                Effect::Action(action) => match action {
+                    ActionEvent::Save => { commands.trigger(save::SaveEvent); },
+                    ActionEvent::SaveAs => {
+                        prompt.open(file::PromptKind::SaveAs, current_file.0.display().to_string());
                     },
-                    ActionEvent::AxisChange(axis) => {
-                        commands.send_event(UndoEvent(e));
-                        *edit_mode = EditMode::AdjustRotateAxis(e, *axis);
-                    }
-                    _ => (),
-                }
-            },
-            EditMode::AdjustRotateAxis(e, axis) => {
-                match action {
-                    ActionEvent::AxisChange(new_axis) => {
-                        commands.send_event(UndoEvent(e));
-
-                        if *new_axis == axis {
-                            *edit_mode = EditMode::AdjustRotate(e);
-                        } else {
-                            *edit_mode = EditMode::AdjustRotateAxis(e, *new_axis);
-                        }
+                    ActionEvent::Open => prompt.open(file::PromptKind::Open, String::new()),
+                    ActionEvent::Delete => { commands.trigger(delete::DeleteEvent); },
+                    ActionEvent::Evolve => { commands.trigger(evolve::EvolveEvent); },
+                    ActionEvent::Undo => { commands.trigger(history::HistoryEvent::Undo); },
+                    ActionEvent::Redo => { commands.trigger(history::HistoryEvent::Redo); },
+                    ActionEvent::SelectAll => { commands.trigger(SelectAllEvent); },
+                    ActionEvent::InvertSelection => { commands.trigger(InvertSelectionEvent); },
+                    ActionEvent::TogglePivot => {
+                        pivot.toggle();
+                        info!(":: Pivot: {}", pivot.name());
                     },
-                    ActionEvent::Cancel => {
-                        commands.send_event(UndoEvent(e));
-                        *edit_mode = EditMode::Default;
-                    }
-                    ActionEvent::Confirm => {
-                        *edit_mode = EditMode::Default;
+                    ActionEvent::ToggleSnap => {
+                        snapping.enabled = !snapping.enabled;
+                        info!(":: Snapping {}", if snapping.enabled { "on" } else { "off" });
                     },
+                    ActionEvent::ToggleOutliner => outliner.hidden = !outliner.hidden,
+                    ActionEvent::ToggleHelp => help.0 = !help.0,
                     _ => (),
-                }
-            },
+                },
+            }
         }
 
-        match action {
-            ActionEvent::Save => { commands.trigger(save::SaveEvent); },
-            ActionEvent::Delete => { commands.trigger(delete::DeleteEvent); },
-            _ => (),
+        // adjustments are only recorded once confirmed
+        if adjusting && matches!(*edit_mode, EditMode::Default) {
+            if matches!(action, ActionEvent::Confirm) {
+                history.commit();
+            } else {
+                history.cancel();
+            }
         }
     }
 
@@ -345,21 +730,81 @@ pub fn editor_control(
     }
 }
 
+/// System to type an exact value while adjusting. Enter confirms the adjustment and Backspace removes the last
+/// character, going back to the mouse once nothing is left.
+pub fn typed_value_input(
+    edit_mode: Res<EditMode>,
+    mut typed: ResMut<TypedValue>,
+    mut ev_action: EventWriter<ActionEvent>,
+    mut ev_key: EventReader<KeyboardInput>,
+) {
+    if !edit_mode.takes_typed_value() {
+        typed.0 = None;
+        ev_key.clear();
+        return;
+    }
+
+    for event in ev_key.read() {
+        if !event.state.is_pressed() {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Character(c) if c.as_str() == "-" => {
+                let text = typed.0.get_or_insert_with(String::new);
+                match text.strip_prefix('-') {
+                    Some(rest) => *text = rest.to_string(),
+                    None => text.insert(0, '-'),
+                }
+            },
+            Key::Character(c) if c.chars().all(|c| c.is_ascii_digit() || c == '.') => {
+                typed.0.get_or_insert_with(String::new).push_str(c);
+            },
+            Key::Backspace => {
+                if let Some(text) = typed.0.as_mut() {
+                    text.pop();
+                    if text.is_empty() {
+                        typed.0 = None;
+                    }
+                }
+            },
+            Key::Enter if typed.0.is_some() => {
+                ev_action.send(ActionEvent::Confirm);
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Reverts the selected joints to where they were when the adjustment started. The structure at that point is kept
+/// in the history until the adjustment is confirmed or cancelled.
 pub fn undo(
-    mut pos_cache: Local<Option<Vec3>>,
+    mut pos_cache: ResMut<AdjustStart>,
+    structure: Res<crate::structure::Structure>,
+    entity_selected: Res<EntitySelected>,
+    mut history: ResMut<history::History>,
     mut ev_undo: EventReader<UndoEvent>,
     mut ev_cache: EventReader<CacheEvent>,
     mut transform_q: Query<&mut Transform>,
 ) {
     for event in ev_undo.read() {
-        if let Some(position) = *pos_cache {
-            let mut transform = transform_q.get_mut(event.0).unwrap();
-            transform.translation = position;
+        // the cache belongs to another adjustment
+        if !pos_cache.0.iter().any(|(joint, _)| *joint == event.0) {
+            continue;
+        }
+        for (joint, position) in pos_cache.0.iter() {
+            if let Ok(mut transform) = transform_q.get_mut(*joint) {
+                transform.translation = *position;
+            }
         }
     }
 
     for event in ev_cache.read() {
-        let transform = transform_q.get(event.0).unwrap();
-        *pos_cache = Some(transform.translation);
+        pos_cache.0.clear();
+        for joint in entity_selected.joints().chain(std::iter::once(event.0)) {
+            if let Ok(transform) = transform_q.get(joint) {
+                pos_cache.0.push((joint, transform.translation));
+            }
+        }
+        history.begin(&structure);
     }
 }
\ No newline at end of file
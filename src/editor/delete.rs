@@ -1,96 +1,117 @@
-use std::collections::HashSet;
-
 use bevy::prelude::*;
-use petgraph::visit::EdgeRef;
+use petgraph::{graph::EdgeIndex, visit::EdgeRef};
 
-use crate::{structure::*, selection::EntitySelected};
+use super::history::History;
+use crate::{structure::*, selection::{EntitySelected, SelectableEntity}};
 
 #[derive(Event)]
 pub struct DeleteEvent;
 
-/// System to handle deletion of joints, connectors, and muscles.
+/// System to handle deletion of the selected joints, connectors, and muscles.
 pub fn delete(
     _: Trigger<DeleteEvent>,
     mut commands: Commands,
     mut structure: ResMut<Structure>,
+    mut history: ResMut<History>,
     mut entity_selected: ResMut<EntitySelected>,
-    joint_q: Query<&mut Joint>,
+    joint_q: Query<&Joint>,
     connector_q: Query<&Connector>,
     muscle_q: Query<&Muscle>,
 ) {
-    if entity_selected.is_joint() { // delete joint and its relatives
-        let joint = entity_selected.get().unwrap();
-        let joint_info = joint_q.get(joint).unwrap().clone();
-
-        let mut paired_edges = Vec::new(); // edges with muscles to edges that are deleted
-        let mut completed_muscles: HashSet<Entity> = HashSet::new(); // muscles that have already been deleted
-        let mut orphans = Vec::new(); // joints whose parents have potentially been deleted
-
-        info!(":: Deleted Joint: {:?}", joint);
+    if entity_selected.is_none() {
+        return;
+    }
+    history.record(&structure);
 
-        for edge in structure.edges(joint_info.node_index) {
-            let muscles = &edge.weight().muscles;
-            for (opp_edge, muscle) in muscles {
-                if completed_muscles.contains(muscle) {
-                    continue;
-                }
-                paired_edges.push((*opp_edge, edge.id()));
-                commands.entity(*muscle).despawn();
-                completed_muscles.insert(*muscle);
-            }
-            let connector = structure.edge_to_entity(edge.id()).unwrap();
-            commands.entity(connector).despawn();
-            
-            let endpoint = if edge.source() == joint_info.node_index {
-                edge.target()
-            } else {
-                edge.source()
-            };
-            orphans.push(endpoint);
+    // muscles first and joints last, so nothing is despawned twice
+    let selected: Vec<SelectableEntity> = entity_selected.iter().cloned().collect();
+    for entity in selected.iter() {
+        if let SelectableEntity::Muscle(muscle) = entity {
+            delete_muscle(&mut commands, &mut structure, &muscle_q, *muscle);
         }
-        for (alive, dead) in paired_edges {
-            let weight = structure.edge_weight_mut(alive).unwrap();
-            weight.muscles.remove(&dead);
+    }
+    for entity in selected.iter() {
+        if let SelectableEntity::Connector(connector) = entity {
+            delete_connector(&mut commands, &mut structure, &connector_q, *connector);
         }
-        for orphan in orphans {
-            let weight = structure.node_weight_mut(orphan).unwrap();
-            if weight.parent == Some(joint_info.node_index) {
-                weight.parent = None;
-            }
+    }
+    for entity in selected.iter() {
+        if let SelectableEntity::Joint(joint) = entity {
+            delete_joint(&mut commands, &mut structure, &joint_q, *joint);
         }
-        commands.entity(joint).despawn();
-        structure.remove_node(joint_info.node_index);
+    }
 
-        entity_selected.set(None);
-    } else if entity_selected.is_connector() { // delete connector
-        let connector = entity_selected.get().unwrap();
-        let conn_info = connector_q.get(connector).unwrap();
-        let weight = structure.edge_weight(conn_info.edge_index).unwrap().clone();
-        for (opp_edge, muscle) in weight.muscles.iter() {
-            let opp_weight = structure.edge_weight_mut(*opp_edge).unwrap();
-            opp_weight.muscles.remove(&conn_info.edge_index);
-            commands.entity(*muscle).despawn();
-        }
-        let (j1, j2) = structure.edge_endpoints(conn_info.edge_index).unwrap();
-        if structure.node_weight(j1).unwrap().parent == Some(j2) {
-            structure.node_weight_mut(j1).unwrap().parent = None;
-        }
-        if structure.node_weight(j2).unwrap().parent == Some(j1) {
-            structure.node_weight_mut(j2).unwrap().parent = None;
-        }
-        structure.remove_edge(conn_info.edge_index);
-        info!(":: Deleted Joint: {:?}", connector);
-        commands.entity(connector).despawn();
-    } else if entity_selected.is_muscle() { // delete muscle
-        let muscle = entity_selected.get().unwrap();
-        let muscle_info = muscle_q.get(muscle).unwrap();
-        let a1 = structure.edge_weight_mut(muscle_info.anchor1.unwrap()).unwrap();
-        a1.muscles.remove(&muscle_info.anchor2.unwrap());
-        let a2 = structure.edge_weight_mut(muscle_info.anchor2.unwrap()).unwrap();
-        a2.muscles.remove(&muscle_info.anchor1.unwrap());
+    entity_selected.set(None);
+}
+
+/// Deletes a joint along with its connectors and their muscles.
+fn delete_joint(
+    commands: &mut Commands,
+    structure: &mut Structure,
+    joint_q: &Query<&Joint>,
+    joint: Entity,
+) {
+    let node = joint_q.get(joint).unwrap().node_index;
+    if structure.node_weight(node).is_none() {
+        return;
+    }
+
+    let edges: Vec<EdgeIndex> = structure.edges(node).map(|e| e.id()).collect();
+    for edge in edges {
+        despawn_connector(commands, structure, edge);
+    }
+    structure.remove_point(node);
+    info!(":: Deleted Joint: {:?}", joint);
+    commands.entity(joint).despawn();
+}
+
+/// Deletes a connector along with its muscles.
+fn delete_connector(
+    commands: &mut Commands,
+    structure: &mut Structure,
+    connector_q: &Query<&Connector>,
+    connector: Entity,
+) {
+    let edge = connector_q.get(connector).unwrap().edge_index;
+    if structure.edge_weight(edge).is_none() {
+        return;
+    }
+
+    despawn_connector(commands, structure, edge);
+    structure.remove_connection(edge);
+    info!(":: Deleted Connector: {:?}", connector);
+}
 
+/// Despawns the connector and its muscles, and forgets the muscles on their other anchors so they aren't despawned
+/// twice. The graph is left to `Structure::remove_connection` and `Structure::remove_point`.
+fn despawn_connector(commands: &mut Commands, structure: &mut Structure, edge: EdgeIndex) {
+    let muscles = std::mem::take(&mut structure[edge].muscles);
+    for (other, muscle) in muscles {
+        if let Some(weight) = structure.edge_weight_mut(other) {
+            weight.muscles.remove(&edge);
+        }
         commands.entity(muscle).despawn();
-        info!(":: Deleted Muscle: {:?}", muscle);
-        entity_selected.set(None);
     }
-}
\ No newline at end of file
+    if let Some(connector) = structure[edge].entityid {
+        commands.entity(connector).despawn();
+    }
+}
+
+/// Deletes a muscle.
+fn delete_muscle(
+    commands: &mut Commands,
+    structure: &mut Structure,
+    muscle_q: &Query<&Muscle>,
+    muscle: Entity,
+) {
+    let muscle_info = muscle_q.get(muscle).unwrap();
+    let (anchor1, anchor2) = (muscle_info.anchor1.unwrap(), muscle_info.anchor2.unwrap());
+    let a1 = structure.edge_weight_mut(anchor1).unwrap();
+    a1.muscles.remove(&anchor2);
+    let a2 = structure.edge_weight_mut(anchor2).unwrap();
+    a2.muscles.remove(&anchor1);
+    structure.remove_muscle_data(anchor1, anchor2);
+
+    commands.entity(muscle).despawn();
+    info!(":: Deleted Muscle: {:?}", muscle);
+}
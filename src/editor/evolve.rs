@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+
+use crate::{
+    evolution::{Evolution, EvolutionConfig},
+    structure::Structure,
+};
+
+use super::history::History;
+
+#[derive(Event)]
+pub struct EvolveEvent;
+
+/// Evolution currently running in the editor, if any.
+#[derive(Resource, Default)]
+pub struct EvolutionRun(pub Option<Evolution>);
+
+/// Starts an evolution seeded with the current structure. Triggers with EvolveEvent.
+pub fn start_evolution(
+    _: Trigger<EvolveEvent>,
+    mut structure: ResMut<Structure>,
+    mut run: ResMut<EvolutionRun>,
+) {
+    if run.0.is_some() {
+        info!(":: Evolution already running");
+        return;
+    }
+    structure.sync_muscle_data();
+    run.0 = Some(Evolution::new(&structure, EvolutionConfig::default()));
+    info!(":: Evolution started");
+}
+
+/// Evaluates one individual per frame so the editor stays responsive, and moves on to the next generation once all of
+/// them are evaluated. Once finished, the structure is replaced by the best individual.
+pub fn evolution_step(
+    mut commands: Commands,
+    mut structure: ResMut<Structure>,
+    mut history: ResMut<History>,
+    mut run: ResMut<EvolutionRun>,
+) {
+    let Some(evolution) = run.0.as_mut() else {
+        return;
+    };
+
+    if evolution.evaluate_next() {
+        return;
+    }
+    let report = evolution.step();
+    info!(":: {}", report);
+
+    if !evolution.is_finished() {
+        return;
+    }
+
+    if let Some(best) = evolution.best() {
+        history.record(&structure);
+        structure.0 = best.structure.0.clone();
+        commands.trigger(super::RespawnEvent);
+        info!(":: Evolution finished, best fitness {:.3}", best.fitness.unwrap_or_default());
+    }
+    run.0 = None;
+}
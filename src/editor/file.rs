@@ -0,0 +1,265 @@
+use core::fmt;
+use std::path::{Path, PathBuf};
+
+use bevy::{input::keyboard::{Key, KeyboardInput}, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    autosave::{newer_autosave, QuitConfirm, RestoreOffer},
+    history::History,
+    muscle::MuscleLabelEvent,
+    save::SaveAsEvent,
+    ui::StatusMessage,
+    RespawnEvent,
+};
+use crate::{format::{FormatError, Metadata, StructureFile, FORMAT_VERSION}, structure::Structure};
+
+/// File opened when none is given on the command line.
+pub const DEFAULT_FILE: &str = "./structure.ron";
+/// File the recent files list is kept in.
+const RECENT_FILES: &str = "./recent_files.ron";
+pub const MAX_RECENT_FILES: usize = 8;
+/// Most issues of a structure listed on screen, the rest are only logged.
+const MAX_SHOWN_ISSUES: usize = 5;
+
+/// File the structure is loaded from and saved to.
+#[derive(Resource)]
+pub struct CurrentFile(pub PathBuf);
+
+impl CurrentFile {
+    /// Name of the file without its directory.
+    pub fn name(&self) -> String {
+        self.0.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.0.display().to_string())
+    }
+}
+
+/// Whether the current file failed to load. The structure started in its place is only saved under a new name, so
+/// the file isn't replaced by it.
+#[derive(Resource, Default)]
+pub struct LoadFailed(pub bool);
+
+/// Files most recently opened or saved, newest first.
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct RecentFiles(pub Vec<PathBuf>);
+
+impl RecentFiles {
+    /// Reads the recent files list. Starts an empty list if there is none.
+    pub fn load() -> Self {
+        std::fs::read(RECENT_FILES)
+            .ok()
+            .and_then(|data| ron::de::from_bytes(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Moves `path` to the front of the list and writes the list to disk.
+    pub fn push(&mut self, path: &Path) {
+        self.0.retain(|p| p != path);
+        self.0.insert(0, path.to_path_buf());
+        self.0.truncate(MAX_RECENT_FILES);
+
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| e.to_string())
+            .and_then(|data| std::fs::write(RECENT_FILES, data).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Failed to write {}: {}", RECENT_FILES, e);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    Open,
+    SaveAs,
+    /// Label of the muscle entity.
+    MuscleLabel(Entity),
+}
+
+/// Text prompt asking for a file path, or for the label of a muscle. Editor controls are disabled while it is open.
+#[derive(Resource, Default)]
+pub struct PathPrompt(pub Option<Prompt>);
+
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub text: String,
+}
+
+impl PathPrompt {
+    pub fn is_open(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn open(&mut self, kind: PromptKind, text: String) {
+        self.0 = Some(Prompt { kind, text });
+    }
+}
+
+#[derive(Event)]
+pub struct OpenEvent {
+    pub path: PathBuf,
+    /// Opens the file even if there are unsaved changes, which are lost.
+    pub discard_changes: bool,
+}
+
+pub enum LoadError {
+    /// Error: File does not exist.
+    Missing(PathBuf),
+    /// Error: File could not be read.
+    Read(PathBuf, std::io::Error),
+    /// Error: File is not a valid structure.
+    Parse(PathBuf, ron::error::SpannedError),
+    /// Error: File was written in a newer format version.
+    Version(PathBuf, u32),
+    /// Error: Elements of the file refer to each other by ids that don't match.
+    Ids(PathBuf, FormatError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Missing(path) =>
+                write!(f, "(Missing): {} does not exist", path.display()),
+            LoadError::Read(path, e) =>
+                write!(f, "(Read): Failed to read {}: {}", path.display(), e),
+            LoadError::Parse(path, e) =>
+                write!(f, "(Parse): {}:{}:{}: {}", path.display(), e.position.line, e.position.col, e.code),
+            LoadError::Version(path, version) =>
+                write!(f, "(Version): {} has format version {}, newer than the supported version {}",
+                    path.display(), version, FORMAT_VERSION),
+            LoadError::Ids(path, e) =>
+                write!(f, "(Ids): {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Reads a structure file, upgrading files of older format versions.
+pub fn read_structure(path: &Path) -> Result<StructureFile, LoadError> {
+    let data = std::fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => LoadError::Missing(path.to_path_buf()),
+        _ => LoadError::Read(path.to_path_buf(), e),
+    })?;
+    StructureFile::from_ron(&data).map_err(|e| match e {
+        FormatError::Parse(e) => LoadError::Parse(path.to_path_buf(), e),
+        FormatError::Version(version) => LoadError::Version(path.to_path_buf(), version),
+        e => LoadError::Ids(path.to_path_buf(), e),
+    })
+}
+
+/// Logs the issues `Structure::validate` finds in the structure of the given file and lists them on screen.
+pub fn report_issues(structure: &Structure, path: &Path, message: &mut StatusMessage) {
+    let issues = structure.validate();
+    if issues.is_empty() {
+        return;
+    }
+    for issue in issues.iter() {
+        warn!("{}: {}", path.display(), issue);
+    }
+
+    let mut text = format!("{} has {} issue(s):", path.display(), issues.len());
+    for issue in issues.iter().take(MAX_SHOWN_ISSUES) {
+        text.push_str(&format!("\n{}", issue));
+    }
+    if issues.len() > MAX_SHOWN_ISSUES {
+        text.push_str(&format!("\n...and {} more", issues.len() - MAX_SHOWN_ISSUES));
+    }
+    message.set(text);
+}
+
+/// Replaces the structure with the one in the given file. The current structure is kept if the file can't be
+/// loaded. Asks whether to save unsaved changes first. Triggers with OpenEvent.
+pub fn open(
+    trigger: Trigger<OpenEvent>,
+    mut commands: Commands,
+    mut structure: ResMut<Structure>,
+    mut metadata: ResMut<Metadata>,
+    mut current_file: ResMut<CurrentFile>,
+    mut recent: ResMut<RecentFiles>,
+    mut history: ResMut<History>,
+    mut offer: ResMut<RestoreOffer>,
+    mut load_failed: ResMut<LoadFailed>,
+    mut quit: ResMut<QuitConfirm>,
+    mut message: ResMut<StatusMessage>,
+) {
+    let OpenEvent { path, discard_changes } = trigger.event();
+    if history.is_dirty() && !discard_changes {
+        quit.ask(Some(path.clone()));
+        return;
+    }
+    match read_structure(path) {
+        Ok(file) => {
+            *structure = file.structure;
+            *metadata = file.metadata;
+            history.clear();
+            current_file.0 = path.clone();
+            load_failed.0 = false;
+            recent.push(path);
+            offer.0 = newer_autosave(path);
+            report_issues(&structure, path, &mut message);
+            commands.trigger(RespawnEvent);
+            info!(":: Opened {}", path.display());
+        },
+        Err(e) => {
+            error!("{}", e);
+            message.set(e.to_string());
+        },
+    }
+}
+
+/// System to edit the text of the path prompt. Enter opens or saves to the typed path, or sets the typed label, and
+/// Escape closes the prompt.
+pub fn prompt_input(
+    mut commands: Commands,
+    mut prompt: ResMut<PathPrompt>,
+    mut ev_key: EventReader<KeyboardInput>,
+) {
+    // events are always read so keys pressed before the prompt opened don't end up in it
+    for event in ev_key.read() {
+        let Some(current) = prompt.0.as_mut() else {
+            continue;
+        };
+        if !event.state.is_pressed() {
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Character(c) => current.text.push_str(c),
+            Key::Space => current.text.push(' '),
+            Key::Backspace => {
+                current.text.pop();
+            },
+            Key::Escape => prompt.0 = None,
+            Key::Enter => {
+                let path = PathBuf::from(current.text.trim());
+                match current.kind {
+                    PromptKind::Open => commands.trigger(OpenEvent { path, discard_changes: false }),
+                    PromptKind::SaveAs => commands.trigger(SaveAsEvent(path)),
+                    PromptKind::MuscleLabel(muscle) => commands.trigger(MuscleLabelEvent {
+                        muscle,
+                        label: current.text.clone(),
+                    }),
+                }
+                prompt.0 = None;
+            },
+            _ => (),
+        }
+    }
+}
+
+/// System to show the name of the current file in the window title.
+pub fn update_window_title(
+    current_file: Res<CurrentFile>,
+    history: Res<History>,
+    mut window_q: Query<&mut Window, With<bevy::window::PrimaryWindow>>,
+) {
+    if !current_file.is_changed() && !history.is_changed() {
+        return;
+    }
+    if let Ok(mut window) = window_q.get_single_mut() {
+        let dirty = if history.is_dirty() { "*" } else { "" };
+        let title = format!("evol - {}{}", current_file.name(), dirty);
+        if window.title != title {
+            window.title = title;
+        }
+    }
+}
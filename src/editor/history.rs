@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use super::RespawnEvent;
+use crate::structure::Structure;
+
+/// Number of changes that can be undone.
+pub const HISTORY_DEPTH: usize = 100;
+
+#[derive(Event)]
+pub enum HistoryEvent {
+    Undo,
+    Redo,
+}
+
+/// Undo and redo history of the structure. Every structural change records a snapshot of the structure as it was
+/// before the change, and undoing it respawns the editor entities from the snapshot.
+#[derive(Resource)]
+pub struct History {
+    undo: VecDeque<Structure>,
+    redo: Vec<Structure>,
+    /// Snapshot taken when an adjustment started, recorded once it is confirmed.
+    pending: Option<Structure>,
+    /// Number of changes made, undone or redone.
+    changes: usize,
+    /// Length of the undo stack when the structure was last saved or loaded. None once that state can't be reached
+    /// by undoing or redoing anymore.
+    saved: Option<usize>,
+    pub max_depth: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            pending: None,
+            changes: 0,
+            saved: Some(0),
+            max_depth: HISTORY_DEPTH,
+        }
+    }
+}
+
+impl History {
+    /// Records the structure before a change. Clears the redo history.
+    pub fn record(&mut self, structure: &Structure) {
+        // a saved state that was undone is dropped with the redo history
+        if self.saved.is_some_and(|saved| saved > self.undo.len()) {
+            self.saved = None;
+        }
+        self.undo.push_back(structure.clone());
+        self.changes += 1;
+        while self.undo.len() > self.max_depth {
+            self.undo.pop_front();
+            self.saved = self.saved.and_then(|saved| saved.checked_sub(1));
+        }
+        self.redo.clear();
+    }
+
+    /// Keeps the structure before a change that may still be cancelled, such as an adjustment.
+    pub fn begin(&mut self, structure: &Structure) {
+        self.pending = Some(structure.clone());
+    }
+
+    /// Records the structure kept by `begin`.
+    pub fn commit(&mut self) {
+        if let Some(structure) = self.pending.take() {
+            self.record(&structure);
+        }
+    }
+
+    /// Drops the structure kept by `begin`.
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.pending = None;
+        self.changes = 0;
+        self.saved = Some(0);
+    }
+
+    /// Marks the current structure as saved.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.undo.len());
+    }
+
+    /// Whether the structure changed since it was last saved or loaded.
+    pub fn is_dirty(&self) -> bool {
+        self.saved != Some(self.undo.len())
+    }
+
+    /// Number of changes made so far, used to tell whether anything changed since a given point.
+    pub fn changes(&self) -> usize {
+        self.changes
+    }
+
+    /// Returns the structure before the last change, keeping `current` to be redone.
+    pub fn undo(&mut self, current: &Structure) -> Option<Structure> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current.clone());
+        self.changes += 1;
+        Some(previous)
+    }
+
+    /// Returns the structure after the last undone change, keeping `current` to be undone again.
+    pub fn redo(&mut self, current: &Structure) -> Option<Structure> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current.clone());
+        self.changes += 1;
+        Some(next)
+    }
+}
+
+/// Undoes or redoes the last change. Triggers with HistoryEvent.
+pub fn apply_history(
+    trigger: Trigger<HistoryEvent>,
+    mut commands: Commands,
+    mut structure: ResMut<Structure>,
+    mut history: ResMut<History>,
+) {
+    let restored = match trigger.event() {
+        HistoryEvent::Undo => history.undo(&structure),
+        HistoryEvent::Redo => history.redo(&structure),
+    };
+    let Some(restored) = restored else {
+        info!(":: Nothing to {}", match trigger.event() {
+            HistoryEvent::Undo => "undo",
+            HistoryEvent::Redo => "redo",
+        });
+        return;
+    };
+
+    *structure = restored;
+    commands.trigger(RespawnEvent);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_back_to_saved_is_clean() {
+        let structure = Structure::default();
+        let mut history = History::default();
+
+        history.record(&structure);
+        history.mark_saved();
+        history.record(&structure);
+        assert!(history.is_dirty());
+
+        history.undo(&structure);
+        assert!(!history.is_dirty());
+        history.undo(&structure);
+        assert!(history.is_dirty());
+        history.redo(&structure);
+        assert!(!history.is_dirty());
+
+        // the saved state can't be reached once a new change replaces it
+        history.undo(&structure);
+        history.record(&structure);
+        assert!(history.is_dirty());
+        history.undo(&structure);
+        assert!(history.is_dirty());
+    }
+}
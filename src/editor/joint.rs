@@ -1,6 +1,6 @@
 use bevy::{picking::pointer::PointerInteraction, prelude::*};
 
-use super::controls::EditMode;
+use super::{controls::EditMode, history::History, snap::Snapping};
 use crate::{
     editor::controls::ActionEvent, 
     selection::{EntitySelected, SelectableEntity}, 
@@ -14,14 +14,19 @@ pub struct JointAddEvent;
 #[derive(Event)]
 pub struct JointLinkEvent;
 
+#[derive(Event)]
+pub struct SubdivideEvent;
+
 /// System to handle the addition of new joints.
 pub fn joint_add(
     mut commands: Commands,
     mut structure: ResMut<Structure>,
+    mut history: ResMut<History>,
     mut entity_selected: ResMut<EntitySelected>,
     joint_materials: Res<JointMaterial>,
     joint_meshes: Res<JointMeshes>,
     edit_mode: Res<EditMode>,
+    snapping: Res<Snapping>,
     mut ev_joint_add: EventReader<JointAddEvent>,
     mut gizmo: Gizmos,
     joint_q: Query<&Joint>,
@@ -53,26 +58,32 @@ pub fn joint_add(
         // Create the new joint where clicked.
         if !ev_joint_add.is_empty() {
             ev_joint_add.clear();
+            history.record(&structure);
 
-            let len = 2.0; // default extension
+            let point = Point::default();
+            let len = 2.0 * point.radius; // default extension
+            let pos = snapping.position(hit_pos + hit_normal * len);
 
             let new_joint = create_joint(
                 &mut commands, 
                 &joint_meshes, 
                 &joint_materials, 
-                hit_pos + hit_normal * len, 
+                pos, 
+                point.radius,
                 None,
                 (),
                 crate::Editor
             );
 
             let parent_data = joint_q.get(joint).unwrap();
+            let parent_radius = structure[parent_data.node_index].radius;
 
-            let node = structure.add_node(
+            let node = structure.add_point(
                 Point { 
                     entityid: Some(new_joint), 
                     parent: Some(parent_data.node_index),
-                    pos: hit_pos + hit_normal * len,
+                    pos,
+                    ..point
                 }
             );
             commands.entity(new_joint).insert(Joint { node_index: node });
@@ -81,14 +92,15 @@ pub fn joint_add(
                 &mut commands, 
                 &joint_meshes, 
                 &joint_materials, 
-                hit_pos + hit_normal * len, 
-                hit_pos + hit_normal * -crate::util::JOINT_RADIUS, 
+                pos, 
+                hit_pos + hit_normal * -parent_radius, 
+                &JointType::default(),
                 None,
                 (),
                 crate::Editor
             );
 
-            let edge = structure.add_edge(node, parent_data.node_index, Connection {
+            let edge = structure.add_connection(node, parent_data.node_index, Connection {
                 entityid: Some(connector),
                 ..default()
             });
@@ -108,6 +120,7 @@ pub fn joint_add(
 pub fn joint_link(
     mut commands: Commands,
     mut structure: ResMut<Structure>,
+    mut history: ResMut<History>,
     edit_mode: Res<EditMode>,
     entity_selected: Res<EntitySelected>,
     meshes: Res<JointMeshes>,
@@ -136,6 +149,8 @@ pub fn joint_link(
         return;
     }
 
+    history.record(&structure);
+
     structure.0.node_weight_mut(j1).unwrap().parent = Some(j2);
 
     if structure.0.contains_edge(j1, j2) {
@@ -153,12 +168,13 @@ pub fn joint_link(
         &materials, 
         j1_pos,
         j2_pos,
+        &JointType::default(),
         None,
         (),
         crate::Editor
     );
 
-    let edge = structure.0.add_edge(j1, j2, Connection {
+    let edge = structure.add_connection(j1, j2, Connection {
         entityid: Some(connector),
         ..default()
     });
@@ -167,6 +183,88 @@ pub fn joint_link(
     info!(":: Link created: {:?} <> {:?}", j1, j2);
 }
 
+/// System to insert a joint along the connector being subdivided, where it is clicked. The new joint is placed at the
+/// clicked point projected onto the connector, keeping the joints of both halves from overlapping when possible.
+pub fn connector_subdivide(
+    mut commands: Commands,
+    mut structure: ResMut<Structure>,
+    mut history: ResMut<History>,
+    edit_mode: Res<EditMode>,
+    snapping: Res<Snapping>,
+    mut ev_subdivide: EventReader<SubdivideEvent>,
+    mut gizmo: Gizmos,
+    connector_q: Query<&Connector>,
+    interaction_q: Query<&PointerInteraction>,
+) {
+    let EditMode::Subdivide(connector) = *edit_mode else {
+        return;
+    };
+    let Ok(edge) = connector_q.get(connector).map(|c| c.edge_index) else {
+        return;
+    };
+    let Some((a, b)) = structure.edge_endpoints(edge) else {
+        return;
+    };
+
+    for interaction in interaction_q.iter() {
+        let Some((target, hit)) = interaction.get_nearest_hit() else {
+            return;
+        };
+
+        // Cancel if something other than the connector being subdivided is clicked.
+        if *target != connector {
+            if !ev_subdivide.is_empty() {
+                ev_subdivide.clear();
+                commands.send_event(ActionEvent::Cancel);
+            }
+            return;
+        }
+
+        let start = structure[a].pos;
+        let offset = structure[b].pos - start;
+        let len = offset.length();
+        let dir = offset.normalize_or_zero();
+        // the halves are kept long enough for the new joint not to overlap the ends
+        let radius = Point::default().radius;
+        let min_a = (structure[a].radius + radius).min(len / 2.0);
+        let min_b = (structure[b].radius + radius).min(len / 2.0);
+        let dist = snapping.length((hit.position.unwrap() - start).dot(dir)).clamp(min_a, len - min_b);
+        let pos = start + dir * dist;
+
+        gizmo.sphere(Isometry3d::from_translation(pos), radius, Color::srgb(1.0, 0.0, 0.0));
+
+        if !ev_subdivide.is_empty() {
+            ev_subdivide.clear();
+            history.record(&structure);
+
+            let Some(node) = structure.subdivide(edge, pos) else {
+                return;
+            };
+            let id = structure[node].id;
+            info!(":: Connector subdivided: {:?} <> {:?} at {:?}", a, b, node);
+
+            // the connector and its muscles are replaced, so the entities are rebuilt with the new joint selected
+            commands.trigger(super::RespawnEvent);
+            commands.trigger(super::watch::ReselectEvent(vec![super::watch::SelectedId::Joint(id)]));
+        }
+    }
+}
+
+/// Draws the axis of every hinge connector through its middle.
+pub fn draw_hinge_axes(
+    structure: Res<Structure>,
+    mut gizmo: Gizmos,
+    connector_q: Query<(&Connector, &Transform)>,
+) {
+    for (connector, transform) in connector_q.iter() {
+        let Some(JointType::Hinge { axis }) = structure.edge_weight(connector.edge_index).map(|c| &c.joint) else {
+            continue;
+        };
+        let axis = axis.normalize_or_zero() * 1.5;
+        gizmo.line(transform.translation - axis, transform.translation + axis, Color::srgb(0.3, 0.5, 0.9));
+    }
+}
+
 pub fn update_structure_pos(
     mut structure: ResMut<Structure>,
     changed_q: Query<(&Joint, &Transform), Changed<Transform>>,
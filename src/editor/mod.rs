@@ -5,14 +5,22 @@ pub mod muscle;
 pub mod save;
 pub mod delete;
 pub mod ui;
+pub mod evolve;
+pub mod file;
+pub mod history;
+pub mod snap;
+pub mod outliner;
+pub mod autosave;
+pub mod watch;
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::structure::Structure;
+use crate::structure::{Connector, Joint, Muscle, Structure};
 use crate::util::{despawn_all, JointMaterial, JointMeshes};
 use crate::GameState;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
 pub enum UnitAxis {
     X,
     Y,
@@ -44,6 +52,20 @@ impl Plugin for EditorPlugin {
         app.init_resource::<Structure>()
             .init_resource::<controls::EditMode>()
             .init_resource::<controls::EditorControls>()
+            .init_resource::<controls::Pivot>()
+            .init_resource::<controls::AdjustStart>()
+            .init_resource::<controls::TypedValue>()
+            .init_resource::<snap::Snapping>()
+            .init_resource::<outliner::Outliner>()
+            .init_resource::<evolve::EvolutionRun>()
+            .init_resource::<file::PathPrompt>()
+            .init_resource::<file::LoadFailed>()
+            .init_resource::<history::History>()
+            .init_resource::<crate::format::Metadata>()
+            .init_resource::<autosave::RestoreOffer>()
+            .init_resource::<autosave::QuitConfirm>()
+            .init_resource::<watch::FileWatch>()
+            .insert_resource(file::RecentFiles::load())
 
             .add_event::<controls::ActionEvent>()
             .add_event::<controls::CursorControlEvent>()
@@ -51,13 +73,38 @@ impl Plugin for EditorPlugin {
             .add_event::<controls::CacheEvent>()
             .add_event::<joint::JointAddEvent>()
             .add_event::<joint::JointLinkEvent>()
+            .add_event::<joint::SubdivideEvent>()
             .add_event::<muscle::MuscleAddEvent>()
+            .add_event::<muscle::MuscleLabelEvent>()
             .add_event::<save::SaveEvent>()
+            .add_event::<save::SaveAsEvent>()
+            .add_event::<file::OpenEvent>()
+            .add_event::<history::HistoryEvent>()
             .add_event::<delete::DeleteEvent>()
+            .add_event::<evolve::EvolveEvent>()
+            .add_event::<watch::ReloadEvent>()
+            .add_event::<watch::ReselectEvent>()
+            .add_event::<RespawnEvent>()
+
+            .add_systems(Startup, (controls::load_controls, snap::load_snapping, autosave::setup_dialogs))
+
+            .add_systems(
+                Update,
+                (
+                    autosave::close_requested,
+                    autosave::update_dialogs,
+                    autosave::dialog_interact,
+                )
+            )
 
             .add_systems(
                 OnEnter(GameState::Editor), 
-                (deserialize_structure, setup)
+                (
+                    deserialize_structure.run_if(run_once),
+                    spawn_structure.after(deserialize_structure),
+                    setup,
+                    outliner::setup_outliner,
+                )
             )
 
             .add_systems(
@@ -74,11 +121,22 @@ impl Plugin for EditorPlugin {
                     crate::camera::pan_orbit_camera,
                     crate::camera::focus_selected_entity,
 
+                    controls::typed_value_input.before(adjust::adjust_control),
                     adjust::adjust_control,
                     joint::joint_add,
                     joint::joint_link,
+                    joint::connector_subdivide,
+                    joint::draw_hinge_axes,
                     muscle::muscle_construct,
                     muscle::update_muscles,
+                    evolve::evolution_step,
+                    file::prompt_input,
+                    file::update_window_title,
+                    outliner::update_outliner,
+                    outliner::highlight_outliner,
+                    outliner::outliner_interact,
+                    autosave::autosave,
+                    watch::watch_file,
                 ).run_if(in_state(GameState::Editor))
             )
 
@@ -93,10 +151,22 @@ impl Plugin for EditorPlugin {
 
             .add_observer(delete::delete)
             .add_observer(save::save)
+            .add_observer(save::save_as)
+            .add_observer(file::open)
+            .add_observer(muscle::muscle_label)
+            .add_observer(history::apply_history)
+            .add_observer(evolve::start_evolution)
+            .add_observer(respawn_structure)
+            .add_observer(watch::reload)
+            .add_observer(watch::reselect)
 
             .add_systems(
                 OnExit(GameState::Editor), 
-                despawn_all::<crate::Editor>
+                (
+                    despawn_all::<crate::Editor>,
+                    crate::selection::clear_selection,
+                    reset_edit_mode,
+                )
             )
             
             .add_plugins(ui::EditorUiPlugin);
@@ -106,15 +176,82 @@ impl Plugin for EditorPlugin {
 }
 
 
+/// Loads the structure from the current file. Only runs the first time the editor is entered, the in-memory
+/// structure is kept afterwards. A new structure is started if the file is missing or can't be loaded, in which case
+/// the error is shown on screen. An autosave newer than the file is offered to be restored.
 fn deserialize_structure(
+    mut structure: ResMut<Structure>,
+    mut metadata: ResMut<crate::format::Metadata>,
+    current_file: Res<file::CurrentFile>,
+    mut recent: ResMut<file::RecentFiles>,
+    mut offer: ResMut<autosave::RestoreOffer>,
+    mut load_failed: ResMut<file::LoadFailed>,
+    mut message: ResMut<ui::StatusMessage>,
+) {
+    offer.0 = autosave::newer_autosave(&current_file.0);
+    match file::read_structure(&current_file.0) {
+        Ok(loaded) => {
+            *structure = loaded.structure;
+            *metadata = loaded.metadata;
+            recent.push(&current_file.0);
+            file::report_issues(&structure, &current_file.0, &mut message);
+            println!("** GENERATED GRAPH");
+        },
+        Err(file::LoadError::Missing(path)) => {
+            *structure = Structure::with_root();
+            info!(":: {} not found, starting a new structure", path.display());
+        },
+        Err(e) => {
+            // the file is kept as is, the new structure has to be saved under another name
+            *structure = Structure::with_root();
+            load_failed.0 = true;
+            error!("{}", e);
+            message.set(e.to_string());
+        },
+    }
+}
+
+/// Spawns the editor entities of the in-memory structure. `muscle_data` is expected to be up to date, either
+/// freshly loaded or synced when leaving the editor.
+fn spawn_structure(
+    mut commands: Commands,
+    mut structure: ResMut<Structure>,
+    meshes: Res<JointMeshes>,
+    materials: Res<JointMaterial>,
+) {
+    structure.create(
+        &mut commands, 
+        meshes, 
+        materials, 
+        (),
+        (), 
+        (), 
+        crate::Editor
+    );
+}
+
+
+/// Triggers a rebuild of the editor entities after the structure resource has been replaced.
+#[derive(Event)]
+pub struct RespawnEvent;
+
+/// Despawns the editor entities of the previous structure and spawns the current one.
+fn respawn_structure(
+    _: Trigger<RespawnEvent>,
     mut commands: Commands,
     mut structure: ResMut<Structure>,
+    mut entity_selected: ResMut<crate::selection::EntitySelected>,
+    mut edit_mode: ResMut<controls::EditMode>,
     meshes: Res<JointMeshes>,
     materials: Res<JointMaterial>,
+    structure_q: Query<Entity, (With<crate::Editor>, Or<(With<Joint>, With<Connector>, With<Muscle>)>)>,
 ) {
-    let graph_data = &std::fs::read("./structure.ron").unwrap();
-    structure.0 = ron::de::from_bytes(graph_data).unwrap();
-    println!("** GENERATED GRAPH");
+    for e in structure_q.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    entity_selected.set(None);
+    *edit_mode = controls::EditMode::Default;
+
     structure.create(
         &mut commands, 
         meshes, 
@@ -126,6 +263,11 @@ fn deserialize_structure(
     );
 }
 
+fn reset_edit_mode(
+    mut edit_mode: ResMut<controls::EditMode>,
+) {
+    *edit_mode = controls::EditMode::Default;
+}
 
 fn setup(
     mut commands: Commands,
@@ -7,14 +7,22 @@ use crate::{
     util::{JointMaterial, JointMeshes}
 };
 
-use super::controls::EditMode;
+use super::{controls::EditMode, history::History};
 
 #[derive(Event)]
 pub struct MuscleAddEvent;
 
+/// Sets the label of a muscle. An empty label removes it.
+#[derive(Event)]
+pub struct MuscleLabelEvent {
+    pub muscle: Entity,
+    pub label: String,
+}
+
 /// Creates muscles between two connectors.
 pub fn muscle_construct(
     mut structure: ResMut<Structure>,
+    mut history: ResMut<History>,
     mut commands: Commands,
     edit_mode: Res<EditMode>,
     entity_selected: Res<EntitySelected>,
@@ -39,20 +47,22 @@ pub fn muscle_construct(
     let anchor1 = connector1.edge_index;
     let anchor2 = connector2.edge_index;
 
-    let anchor1_data = structure.edge_weight_mut(anchor1).unwrap();
-
-    if anchor1_data.muscles.contains_key(&anchor2) {
+    if structure[anchor1].muscles.contains_key(&anchor2) {
         info!(":: Muscle already exists between edge {:?} and {:?}", anchor1, anchor2);
         commands.send_event(ActionEvent::Cancel);
         return;
     }
 
+    history.record(&structure);
+    let anchor1_data = structure.edge_weight_mut(anchor1).unwrap();
+
     let muscle = create_muscle(
         &mut commands, 
         &meshes, 
         &materials, 
         Some(anchor1), 
         Some(anchor2), 
+        MuscleParams::default(),
         (), 
         crate::Editor
     );
@@ -62,9 +72,35 @@ pub fn muscle_construct(
     let anchor2_data = structure.edge_weight_mut(anchor2).unwrap();
     anchor2_data.muscles.insert(anchor1, muscle);
 
+    structure.add_muscle_data(anchor1, anchor2, MuscleParams::default());
+
     info!(":: Muscle Constructed: {:?} <> {:?}", anchor1, anchor2);
 }
 
+/// Sets the label of the muscle on the entity and on both of its anchors. Triggers with MuscleLabelEvent.
+pub fn muscle_label(
+    trigger: Trigger<MuscleLabelEvent>,
+    mut structure: ResMut<Structure>,
+    mut history: ResMut<History>,
+    mut muscle_q: Query<&mut Muscle>,
+) {
+    let event = trigger.event();
+    let Ok(mut muscle) = muscle_q.get_mut(event.muscle) else {
+        return;
+    };
+    let (Some(anchor1), Some(anchor2)) = (muscle.anchor1, muscle.anchor2) else {
+        return;
+    };
+    let label = Some(event.label.trim().to_string()).filter(|label| !label.is_empty());
+    if muscle.params.label == label {
+        return;
+    }
+
+    history.record(&structure);
+    muscle.params.label = label;
+    structure.set_muscle_params(anchor1, anchor2, muscle.params.clone());
+}
+
 /// Updates the transform of the muscles when they have been created or the anchors have been moved.
 pub fn update_muscles(
     structure: Res<Structure>,
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use petgraph::{graph::NodeIndex, visit::EdgeRef};
+
+use super::{controls::EditMode, ui::{HOVERED_BUTTON, NORMAL_BUTTON}};
+use crate::{
+    selection::{EntitySelected, SelectableEntity, SelectionUpdateEvent},
+    structure::Structure,
+    Editor
+};
+
+/// Indentation of each level of the tree in pixels.
+const INDENT: f32 = 12.0;
+const SELECTED_ROW: Color = Color::srgb(0.45, 0.38, 0.15);
+
+/// State of the outliner panel. `collapsed` joints hide their connectors and children.
+#[derive(Resource, Default)]
+pub struct Outliner {
+    pub collapsed: HashSet<NodeIndex>,
+    pub hidden: bool,
+}
+
+/// Panel listing the joints as a tree by parent, with connectors under the joint they lead from and muscles under
+/// their first connector.
+#[derive(Component)]
+pub struct OutlinerPanel;
+
+/// Row of the outliner selecting its entity when clicked.
+#[derive(Component)]
+pub struct OutlinerEntry(SelectableEntity);
+
+/// Button collapsing or expanding a joint of the outliner.
+#[derive(Component)]
+pub struct OutlinerToggle(NodeIndex);
+
+#[derive(PartialEq)]
+pub struct Row {
+    depth: usize,
+    label: String,
+    entity: Option<SelectableEntity>,
+    /// Joint the row collapses and whether it is collapsed.
+    toggle: Option<(NodeIndex, bool)>,
+}
+
+pub fn setup_outliner(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(25.0),
+            left: Val::Px(5.0),
+            max_height: Val::Percent(70.0),
+            min_width: Val::Px(160.0),
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(5.0)),
+            overflow: Overflow::clip_y(),
+            ..default()
+        },
+        BackgroundColor(NORMAL_BUTTON.with_alpha(0.8)),
+        OutlinerPanel,
+        Editor
+    ));
+}
+
+/// Lists the rows of the outliner, walking the joints from the roots down.
+fn outliner_rows(structure: &Structure, collapsed: &HashSet<NodeIndex>) -> Vec<Row> {
+    let mut roots = Vec::new();
+    let mut children: HashMap<NodeIndex, Vec<NodeIndex>> = HashMap::new();
+    for node in structure.node_indices() {
+        match structure[node].parent.filter(|p| structure.contains_node(*p)) {
+            Some(parent) => children.entry(parent).or_default().push(node),
+            None => roots.push(node),
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut stack: Vec<(NodeIndex, usize)> = roots.into_iter().rev().map(|node| (node, 0)).collect();
+    while let Some((node, depth)) = stack.pop() {
+        // connectors belong to the child joint, or to the lower joint when linking two joints of the tree
+        let connectors: Vec<_> = structure.edges(node)
+            .filter(|edge| {
+                let other = if edge.source() == node { edge.target() } else { edge.source() };
+                structure[node].parent == Some(other)
+                    || (structure[other].parent != Some(node) && node.index() < other.index())
+            })
+            .collect();
+        let kids = children.remove(&node).unwrap_or_default();
+        let is_collapsed = collapsed.contains(&node);
+
+        rows.push(Row {
+            depth,
+            label: format!("Joint {}", node.index()),
+            entity: structure[node].entityid.map(SelectableEntity::Joint),
+            toggle: (!connectors.is_empty() || !kids.is_empty()).then_some((node, is_collapsed)),
+        });
+        if is_collapsed {
+            continue;
+        }
+
+        for edge in connectors {
+            rows.push(Row {
+                depth: depth + 1,
+                label: format!("Connector {} ({} - {})", edge.id().index(), edge.source().index(), edge.target().index()),
+                entity: edge.weight().entityid.map(SelectableEntity::Connector),
+                toggle: None,
+            });
+            for other in edge.weight().muscle_data.keys().filter(|other| edge.id().index() < other.index()) {
+                rows.push(Row {
+                    depth: depth + 2,
+                    label: format!("Muscle {} - {}", edge.id().index(), other.index()),
+                    entity: edge.weight().muscles.get(other).copied().map(SelectableEntity::Muscle),
+                    toggle: None,
+                });
+            }
+        }
+        stack.extend(kids.into_iter().rev().map(|kid| (kid, depth + 1)));
+    }
+    rows
+}
+
+/// System to rebuild the outliner rows when the structure changes or a joint is collapsed.
+pub fn update_outliner(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    structure: Res<Structure>,
+    outliner: Res<Outliner>,
+    mut shown: Local<Vec<Row>>,
+    mut panel_q: Query<(Entity, &mut Node, Ref<OutlinerPanel>)>,
+) {
+    let Ok((panel, mut node, added)) = panel_q.get_single_mut() else {
+        return;
+    };
+    node.display = if outliner.hidden { Display::None } else { Display::Flex };
+    if !structure.is_changed() && !outliner.is_changed() && !added.is_added() {
+        return;
+    }
+
+    let rows = outliner_rows(&structure, &outliner.collapsed);
+    // moving joints changes the structure without changing the rows
+    if rows == *shown && !added.is_added() {
+        return;
+    }
+
+    let font_handle: Handle<Font> = asset_server.load("fonts\\FiraCode-Regular.ttf");
+    let font = TextFont {
+        font: font_handle,
+        font_size: 13.0,
+        ..default()
+    };
+    commands.entity(panel).despawn_descendants().with_children(|parent| {
+        for row in rows.iter() {
+            parent.spawn(Node {
+                align_items: AlignItems::Center,
+                padding: UiRect::left(Val::Px(row.depth as f32 * INDENT)),
+                column_gap: Val::Px(4.0),
+                ..default()
+            }).with_children(|line| {
+                let mut toggle = line.spawn((
+                    Node {
+                        width: Val::Px(14.0),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                ));
+                if let Some((node, collapsed)) = row.toggle {
+                    toggle.insert((Button, OutlinerToggle(node)))
+                        .with_child((Text::new(if collapsed { "+" } else { "-" }), font.clone()));
+                }
+
+                let mut entry = line.spawn((
+                    Node {
+                        flex_grow: 1.0,
+                        padding: UiRect::horizontal(Val::Px(2.0)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::NONE),
+                ));
+                if let Some(entity) = &row.entity {
+                    entry.insert((Button, OutlinerEntry(entity.clone())));
+                }
+                entry.with_child((Text::new(row.label.clone()), font.clone(), TextColor(Color::WHITE)));
+            });
+        }
+    });
+    *shown = rows;
+}
+
+/// System to highlight the rows of the selected entities.
+pub fn highlight_outliner(
+    entity_selected: Res<EntitySelected>,
+    mut entry_q: Query<(&OutlinerEntry, &Interaction, &mut BackgroundColor)>,
+) {
+    for (entry, interaction, mut color) in entry_q.iter_mut() {
+        let new = if entity_selected.contains(entry.0.entity()) {
+            SELECTED_ROW
+        } else if *interaction != Interaction::None {
+            HOVERED_BUTTON
+        } else {
+            Color::NONE
+        };
+        if color.0 != new {
+            color.0 = new;
+        }
+    }
+}
+
+/// System to select the entity of a clicked row, adding it to the selection while shift is held, and to collapse or
+/// expand joints.
+pub fn outliner_interact(
+    mut commands: Commands,
+    edit_mode: Res<EditMode>,
+    key_input: Res<ButtonInput<KeyCode>>,
+    mut entity_selected: ResMut<EntitySelected>,
+    mut outliner: ResMut<Outliner>,
+    entry_q: Query<(&Interaction, &OutlinerEntry), Changed<Interaction>>,
+    toggle_q: Query<(&Interaction, &OutlinerToggle), Changed<Interaction>>,
+) {
+    for (interaction, toggle) in toggle_q.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if !outliner.collapsed.remove(&toggle.0) {
+            outliner.collapsed.insert(toggle.0);
+        }
+    }
+
+    if !matches!(*edit_mode, EditMode::Default) {
+        return;
+    }
+    let shift = key_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for (interaction, entry) in entry_q.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if shift {
+            entity_selected.toggle(entry.0.clone());
+        } else {
+            entity_selected.set(Some(entry.0.clone()));
+        }
+        commands.trigger(SelectionUpdateEvent);
+    }
+}
@@ -1,28 +1,77 @@
+use std::path::PathBuf;
+
 use bevy::prelude::*;
 
-use crate::structure::Structure;
+use super::{
+    autosave::remove_autosave,
+    file::{report_issues, CurrentFile, LoadFailed, PathPrompt, PromptKind, RecentFiles},
+    history::History,
+    ui::StatusMessage,
+    watch::FileWatch,
+};
+use crate::{format::{to_ron, Metadata}, structure::Structure};
 
 #[derive(Event)]
 pub struct SaveEvent;
 
-/// System that saves the joint structure to a file.
+#[derive(Event)]
+pub struct SaveAsEvent(pub PathBuf);
+
+/// System that saves the joint structure to the current file, replacing its autosave. A creature without a name is
+/// named after the file, and one without an author gets the current user. A current file that failed to load isn't
+/// overwritten, the save as prompt is opened instead.
 pub fn save(
     _: Trigger<SaveEvent>,
     mut structure: ResMut<Structure>,
+    mut metadata: ResMut<Metadata>,
+    current_file: Res<CurrentFile>,
+    mut recent: ResMut<RecentFiles>,
+    mut history: ResMut<History>,
+    mut watch: ResMut<FileWatch>,
+    mut prompt: ResMut<PathPrompt>,
+    load_failed: Res<LoadFailed>,
+    mut message: ResMut<StatusMessage>,
 ) {
-    for edge in structure.0.edge_weights_mut() {
-        edge.muscle_data = edge.muscles.keys().copied().collect();
+    let path = &current_file.0;
+    if load_failed.0 {
+        message.set(format!("{} failed to load and is not overwritten, save under another name", path.display()));
+        prompt.open(PromptKind::SaveAs, String::new());
+        return;
+    }
+
+    structure.sync_muscle_data();
+    if metadata.name.is_empty() {
+        metadata.name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
     }
-    std::fs::write(
-        "./structure.ron", 
-        ron::ser::to_string_pretty(
-            &structure.0, 
-                ron::ser::PrettyConfig::new()
-                .depth_limit(2)
-                .separate_tuple_members(true)
-                .enumerate_arrays(true)
-        ).unwrap()
-    ).unwrap();
-
-    info!(":: Saved to ./structure.ron");
-}
\ No newline at end of file
+    if metadata.author.is_empty() {
+        metadata.author = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default();
+    }
+    metadata.touch();
+    let result = to_ron(&structure, &metadata)
+        .map_err(|e| e.to_string())
+        .and_then(|data| std::fs::write(path, data).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        error!("Failed to save to {}: {}", path.display(), e);
+        message.set(format!("Failed to save to {}: {}", path.display(), e));
+        return;
+    }
+
+    history.mark_saved();
+    watch.sync(path);
+    remove_autosave(path);
+    recent.push(path);
+    info!(":: Saved to {}", path.display());
+    report_issues(&structure, path, &mut message);
+}
+
+/// Makes the given path the current file and saves to it. Triggers with SaveAsEvent.
+pub fn save_as(
+    trigger: Trigger<SaveAsEvent>,
+    mut commands: Commands,
+    mut current_file: ResMut<CurrentFile>,
+    mut load_failed: ResMut<LoadFailed>,
+) {
+    current_file.0 = trigger.event().0.clone();
+    load_failed.0 = false;
+    commands.trigger(SaveEvent);
+}
@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::ui::StatusMessage;
+
+/// File the snapping steps are read from.
+const SNAPPING_FILE: &str = "./snapping.ron";
+
+/// Snapping of joint positions, lengths and rotations while placing and adjusting joints. Holding the invert key
+/// snaps while snapping is off, and the other way around. The steps and whether snapping starts enabled are read from
+/// `snapping.ron` if there is one, the defaults are used otherwise.
+#[derive(Resource, Deserialize)]
+#[serde(default)]
+pub struct Snapping {
+    pub enabled: bool,
+    /// Whether the invert key is held.
+    #[serde(skip)]
+    pub inverted: bool,
+    /// Spacing of the world grid positions snap to.
+    pub grid: f32,
+    /// Increment connector lengths snap to when extending.
+    pub length: f32,
+    /// Increment rotations snap to, in degrees.
+    pub angle: f32,
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Snapping {
+            enabled: false,
+            inverted: false,
+            grid: 0.5,
+            length: 0.5,
+            angle: 15.0,
+        }
+    }
+}
+
+impl Snapping {
+    /// Reads the snapping steps from the snapping file. Uses the defaults if there is no file.
+    pub fn load() -> Result<Self, String> {
+        match std::fs::read(SNAPPING_FILE) {
+            Ok(data) => ron::de::from_bytes(&data).map_err(|e| {
+                format!("(Parse): {}:{}:{}: {}", SNAPPING_FILE, e.position.line, e.position.col, e.code)
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Snapping::default()),
+            Err(e) => Err(format!("(Read): Failed to read {}: {}", SNAPPING_FILE, e)),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.enabled != self.inverted
+    }
+
+    /// Snaps each coordinate to the grid.
+    pub fn position(&self, pos: Vec3) -> Vec3 {
+        if !self.is_active() || self.grid <= 0.0 {
+            return pos;
+        }
+        (pos / self.grid).round() * self.grid
+    }
+
+    /// Snaps a length to the length increment. Never snaps to zero.
+    pub fn length(&self, len: f32) -> f32 {
+        if !self.is_active() || self.length <= 0.0 {
+            return len;
+        }
+        ((len / self.length).round() * self.length).max(self.length)
+    }
+
+    /// Snaps an angle in radians to the angle increment.
+    pub fn angle(&self, angle: f32) -> f32 {
+        if !self.is_active() || self.angle <= 0.0 {
+            return angle;
+        }
+        let step = self.angle.to_radians();
+        (angle / step).round() * step
+    }
+}
+
+/// Replaces the default snapping steps with the ones in the snapping file, and shows any error.
+pub fn load_snapping(
+    mut snapping: ResMut<Snapping>,
+    mut message: ResMut<StatusMessage>,
+) {
+    match Snapping::load() {
+        Ok(loaded) => *snapping = loaded,
+        Err(e) => {
+            error!("{}", e);
+            message.set(format!("{}, using the default snapping", e));
+        },
+    }
+}
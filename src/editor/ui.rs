@@ -1,11 +1,24 @@
 use bevy::prelude::*;
-use crate::{selection::EntitySelected, Editor, GameState};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use super::{
+    controls::{key_name, ActionEvent, EditMode, EditorControls},
+    file::{PathPrompt, PromptKind, RecentFiles},
+    history::History,
+};
+use crate::{
+    selection::EntitySelected, 
+    structure::{Connection, Connector, Joint, JointType, Muscle, MuscleParams, Point, Structure}, 
+    util::joint_scale,
+    Editor, GameState
+};
 
 
 pub struct EditorUiPlugin;
 impl Plugin for EditorUiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
+        app.init_resource::<StatusMessage>()
+        .init_resource::<KeyHelp>()
+        .add_systems(
             OnEnter(GameState::Editor), 
             init
         )
@@ -13,6 +26,15 @@ impl Plugin for EditorUiPlugin {
             Update,
             (
                 update_pos_info,
+                update_property_panel,
+                property_button_interact,
+                label_button_interact,
+                update_path_prompt,
+                recent_file_interact,
+                update_status_message,
+                status_message_interact,
+                update_key_help,
+                update_mode_hud,
                 tbutton_interact
             ).run_if(in_state(GameState::Editor))
         );
@@ -22,11 +44,400 @@ impl Plugin for EditorUiPlugin {
 #[derive(Component)]
 struct PosText;
 
+/// Text showing the current edit mode and the keys it accepts.
+#[derive(Component)]
+struct ModeText;
+
 #[derive(Component)]
 struct TButton;
 
-const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
-const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
+/// Message shown at the bottom of the editor, such as errors while loading or saving. Stays until clicked.
+#[derive(Resource, Default)]
+pub struct StatusMessage(pub Option<String>);
+
+impl StatusMessage {
+    pub fn set(&mut self, message: String) {
+        self.0 = Some(message);
+    }
+}
+
+#[derive(Component)]
+struct StatusPanel;
+
+/// Whether the list of key bindings is shown.
+#[derive(Resource, Default)]
+pub struct KeyHelp(pub bool);
+
+#[derive(Component)]
+struct HelpPanel;
+
+#[derive(Component)]
+struct StatusText;
+
+/// Panel of the path prompt along with the recent files.
+#[derive(Component)]
+struct PromptPanel;
+
+#[derive(Component)]
+struct PromptText;
+
+/// Button filling the path prompt with the recent file at the index.
+#[derive(Component)]
+struct RecentFileButton(usize);
+
+#[derive(Component)]
+struct RecentFileText(usize);
+
+/// Panel listing the properties of the selected joint, connector or muscle.
+#[derive(Component)]
+struct PropertyPanel;
+
+/// Row of the property panel showing a single field.
+#[derive(Component)]
+struct PropertyRow(PropertyField);
+
+#[derive(Component)]
+struct PropertyText(PropertyField);
+
+/// Button opening the prompt for the label of the selected muscle.
+#[derive(Component)]
+struct LabelButton;
+
+/// Button changing a property by `delta` steps.
+#[derive(Component)]
+struct PropertyButton {
+    field: PropertyField,
+    delta: f32,
+}
+
+#[derive(Clone, Copy)]
+enum PropertyField {
+    Joint(JointField),
+    Connector(ConnectorField),
+    Muscle(MuscleField),
+}
+
+impl PropertyField {
+    fn all() -> impl Iterator<Item = PropertyField> {
+        JointField::ALL.into_iter().map(PropertyField::Joint)
+            .chain(ConnectorField::ALL.into_iter().map(PropertyField::Connector))
+            .chain(MuscleField::ALL.into_iter().map(PropertyField::Muscle))
+    }
+
+    /// Whether the field can be changed through the panel buttons. Connectors are rewired by linking and deleting
+    /// instead, and the label is typed into a prompt.
+    fn editable(self) -> bool {
+        !matches!(self, PropertyField::Connector(ConnectorField::Endpoints) | PropertyField::Muscle(MuscleField::Label))
+    }
+}
+
+/// Smallest mass the panel sets. Joints without mass are fixed in place by the simulation, which only files can ask
+/// for.
+const MIN_MASS: f32 = 0.1;
+
+#[derive(Clone, Copy)]
+enum JointField {
+    X,
+    Y,
+    Z,
+    Parent,
+    Mass,
+    Radius,
+    Friction,
+}
+
+impl JointField {
+    const ALL: [JointField; 7] = [
+        JointField::X,
+        JointField::Y,
+        JointField::Z,
+        JointField::Parent,
+        JointField::Mass,
+        JointField::Radius,
+        JointField::Friction,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            JointField::X => "X",
+            JointField::Y => "Y",
+            JointField::Z => "Z",
+            JointField::Parent => "Parent",
+            JointField::Mass => "Mass",
+            JointField::Radius => "Radius",
+            JointField::Friction => "Friction",
+        }
+    }
+
+    /// Amount a single button press changes the property by.
+    fn step(self) -> f32 {
+        match self {
+            JointField::X | JointField::Y | JointField::Z => 0.1,
+            JointField::Parent => 1.0,
+            JointField::Mass => 0.25,
+            JointField::Radius => 0.1,
+            JointField::Friction => 0.25,
+        }
+    }
+
+    fn value(self, point: &Point) -> String {
+        match self {
+            JointField::X => format!("{:.3}", point.pos.x),
+            JointField::Y => format!("{:.3}", point.pos.y),
+            JointField::Z => format!("{:.3}", point.pos.z),
+            JointField::Parent => point.parent.map_or("none".to_string(), |p| format!("Joint {}", p.index())),
+            JointField::Mass if point.mass <= 0.0 => "fixed".to_string(),
+            JointField::Mass => format!("{:.2}", point.mass),
+            JointField::Radius => format!("{:.2}", point.radius),
+            JointField::Friction => format!("{:.2}", point.friction),
+        }
+    }
+
+    /// Changes the property by `delta` steps. The parent is cycled through the joints connected to it instead, see
+    /// `property_button_interact`.
+    fn apply(self, point: &mut Point, delta: f32) {
+        let change = delta * self.step();
+        match self {
+            JointField::X => point.pos.x += change,
+            JointField::Y => point.pos.y += change,
+            JointField::Z => point.pos.z += change,
+            JointField::Parent => (),
+            JointField::Mass => point.mass = (point.mass + change).max(MIN_MASS),
+            JointField::Radius => point.radius = (point.radius + change).max(0.1),
+            JointField::Friction => point.friction = (point.friction + change).max(0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ConnectorField {
+    Endpoints,
+    Length,
+    Type,
+    Axis,
+    Stiffness,
+    MinAngle,
+    MaxAngle,
+    MinLength,
+    MaxLength,
+}
+
+impl ConnectorField {
+    const ALL: [ConnectorField; 9] = [
+        ConnectorField::Endpoints,
+        ConnectorField::Length,
+        ConnectorField::Type,
+        ConnectorField::Axis,
+        ConnectorField::Stiffness,
+        ConnectorField::MinAngle,
+        ConnectorField::MaxAngle,
+        ConnectorField::MinLength,
+        ConnectorField::MaxLength,
+    ];
+
+    /// Joint types the type field cycles through.
+    const TYPES: [JointType; 4] = [
+        JointType::Rigid,
+        JointType::Ball,
+        JointType::Hinge { axis: Vec3::X },
+        JointType::Spring { stiffness: 50.0, damping: 1.0 },
+    ];
+
+    /// Hinge axes the axis field cycles through.
+    const AXES: [Vec3; 3] = [Vec3::X, Vec3::Y, Vec3::Z];
+
+    fn name(self) -> &'static str {
+        match self {
+            ConnectorField::Endpoints => "Joints",
+            ConnectorField::Length => "Length",
+            ConnectorField::Type => "Type",
+            ConnectorField::Axis => "Hinge axis",
+            ConnectorField::Stiffness => "Stiffness",
+            ConnectorField::MinAngle => "Min angle",
+            ConnectorField::MaxAngle => "Max angle",
+            ConnectorField::MinLength => "Min length",
+            ConnectorField::MaxLength => "Max length",
+        }
+    }
+
+    /// Amount a single button press changes the property by.
+    fn step(self) -> f32 {
+        match self {
+            ConnectorField::Endpoints => 0.0,
+            ConnectorField::Length => 0.25,
+            ConnectorField::Type | ConnectorField::Axis => 1.0,
+            ConnectorField::Stiffness => 5.0,
+            ConnectorField::MinAngle | ConnectorField::MaxAngle => std::f32::consts::PI / 12.0,
+            ConnectorField::MinLength | ConnectorField::MaxLength => 0.25,
+        }
+    }
+
+    /// `length` is the current length of the connector and `ends` the joints it connects.
+    fn value(self, connection: &Connection, length: f32, ends: (NodeIndex, NodeIndex)) -> String {
+        let limits = &connection.limits;
+        let angle = |limit: Option<f32>| limit.map_or("none".to_string(), |a| format!("{:.0}°", a.to_degrees()));
+        let limit_length = |limit: Option<f32>| limit.map_or("none".to_string(), |l| format!("{:.2}", l));
+        match (self, &connection.joint) {
+            (ConnectorField::Endpoints, _) => format!("{} - {}", ends.0.index(), ends.1.index()),
+            (ConnectorField::Length, _) => format!("{:.3}", length),
+            (ConnectorField::Type, joint) => joint.name().to_string(),
+            (ConnectorField::Axis, JointType::Hinge { axis }) => format!("({:.1}, {:.1}, {:.1})", axis.x, axis.y, axis.z),
+            (ConnectorField::Stiffness, JointType::Spring { stiffness, .. }) => format!("{:.1}", stiffness),
+            (ConnectorField::MinAngle, _) => angle(limits.min_angle),
+            (ConnectorField::MaxAngle, _) => angle(limits.max_angle),
+            (ConnectorField::MinLength, JointType::Spring { .. }) => limit_length(limits.min_length),
+            (ConnectorField::MaxLength, JointType::Spring { .. }) => limit_length(limits.max_length),
+            _ => "-".to_string(),
+        }
+    }
+
+    /// Changes the property by `delta` steps. `length` is the current length of the connector, used when a length
+    /// limit is first set. The length itself is changed by moving the joints, see `property_button_interact`.
+    fn apply(self, connection: &mut Connection, delta: f32, length: f32) {
+        let change = delta * self.step();
+        let limits = &mut connection.limits;
+        match (self, &mut connection.joint) {
+            (ConnectorField::Type, joint) => {
+                let current = Self::TYPES.iter().position(|t| t.name() == joint.name()).unwrap_or(0);
+                let next = (current as i32 + delta.signum() as i32).rem_euclid(Self::TYPES.len() as i32);
+                *joint = Self::TYPES[next as usize].clone();
+            },
+            (ConnectorField::Axis, JointType::Hinge { axis }) => {
+                let current = Self::AXES.iter().position(|a| a == axis).unwrap_or(0);
+                let next = (current as i32 + delta.signum() as i32).rem_euclid(Self::AXES.len() as i32);
+                *axis = Self::AXES[next as usize];
+            },
+            (ConnectorField::Stiffness, JointType::Spring { stiffness, .. }) => {
+                *stiffness = (*stiffness + change).max(0.0);
+            },
+            (ConnectorField::MinAngle, _) => {
+                limits.min_angle = step_limit(limits.min_angle, change, 0.0, 0.0..=std::f32::consts::PI);
+            },
+            (ConnectorField::MaxAngle, _) => {
+                limits.max_angle = step_limit(limits.max_angle, change, std::f32::consts::PI, 0.0..=std::f32::consts::PI);
+            },
+            (ConnectorField::MinLength, JointType::Spring { .. }) => {
+                limits.min_length = step_limit(limits.min_length, change, length, 0.0..=f32::INFINITY);
+            },
+            (ConnectorField::MaxLength, JointType::Spring { .. }) => {
+                limits.max_length = step_limit(limits.max_length, change, length, 0.0..=f32::INFINITY);
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Option following `current` in `options`, or preceding it for a negative `delta`. Starts from the first option if
+/// `current` isn't one of them.
+fn cycle<T: PartialEq + Copy>(options: &[T], current: T, delta: f32) -> Option<T> {
+    let next = match options.iter().position(|o| *o == current) {
+        Some(i) => (i as i32 + delta.signum() as i32).rem_euclid(options.len() as i32) as usize,
+        None => 0,
+    };
+    options.get(next).copied()
+}
+
+/// Changes an optional limit by `change`, starting from `start` if it is unset. The limit is unset again once it
+/// leaves `range`.
+fn step_limit(limit: Option<f32>, change: f32, start: f32, range: std::ops::RangeInclusive<f32>) -> Option<f32> {
+    let value = limit.unwrap_or(start) + change;
+    range.contains(&value).then_some(value)
+}
+
+#[derive(Clone, Copy)]
+enum MuscleField {
+    Anchor1,
+    Anchor2,
+    MaxForce,
+    RestLength,
+    Stiffness,
+    Damping,
+    Phase,
+    Frequency,
+    Label,
+}
+
+impl MuscleField {
+    const ALL: [MuscleField; 9] = [
+        MuscleField::Anchor1,
+        MuscleField::Anchor2,
+        MuscleField::MaxForce,
+        MuscleField::RestLength,
+        MuscleField::Stiffness,
+        MuscleField::Damping,
+        MuscleField::Phase,
+        MuscleField::Frequency,
+        MuscleField::Label,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            MuscleField::Anchor1 => "Connector 1",
+            MuscleField::Anchor2 => "Connector 2",
+            MuscleField::MaxForce => "Max force",
+            MuscleField::RestLength => "Rest length",
+            MuscleField::Stiffness => "Stiffness",
+            MuscleField::Damping => "Damping",
+            MuscleField::Phase => "Phase",
+            MuscleField::Frequency => "Frequency",
+            MuscleField::Label => "Label",
+        }
+    }
+
+    /// Amount a single button press changes the parameter by.
+    fn step(self) -> f32 {
+        match self {
+            MuscleField::Anchor1 | MuscleField::Anchor2 => 1.0,
+            MuscleField::Label => 0.0,
+            MuscleField::MaxForce => 10.0,
+            MuscleField::RestLength => 0.25,
+            MuscleField::Stiffness => 5.0,
+            MuscleField::Damping => 0.5,
+            MuscleField::Phase => std::f32::consts::PI / 8.0,
+            MuscleField::Frequency => 0.1,
+        }
+    }
+
+    fn value(self, muscle: &Muscle) -> String {
+        let params = &muscle.params;
+        let anchor = |anchor: Option<EdgeIndex>| anchor.map_or("none".to_string(), |a| a.index().to_string());
+        match self {
+            MuscleField::Anchor1 => anchor(muscle.anchor1),
+            MuscleField::Anchor2 => anchor(muscle.anchor2),
+            MuscleField::MaxForce => format!("{:.1}", params.max_force),
+            MuscleField::RestLength => match params.rest_length {
+                Some(len) => format!("{:.2}", len),
+                None => "auto".to_string(),
+            },
+            MuscleField::Stiffness => format!("{:.1}", params.stiffness),
+            MuscleField::Damping => format!("{:.2}", params.damping),
+            MuscleField::Phase => format!("{:.1}°", params.phase.to_degrees()),
+            MuscleField::Frequency => format!("{:.2}", params.frequency),
+            MuscleField::Label => params.label.clone().unwrap_or_else(|| "none".to_string()),
+        }
+    }
+
+    /// Changes the parameter by `delta` steps. `length` is the current length of the muscle, used when the rest
+    /// length is first set. The anchors are cycled through the connectors next to the other anchor instead, see
+    /// `property_button_interact`, and the label is typed into a prompt, see `label_button_interact`.
+    fn apply(self, params: &mut MuscleParams, delta: f32, length: f32) {
+        let change = delta * self.step();
+        match self {
+            MuscleField::Anchor1 | MuscleField::Anchor2 | MuscleField::Label => (),
+            MuscleField::MaxForce => params.max_force = (params.max_force + change).max(0.0),
+            MuscleField::RestLength => {
+                params.rest_length = Some((params.rest_length.unwrap_or(length) + change).max(0.0));
+            },
+            MuscleField::Stiffness => params.stiffness = (params.stiffness + change).max(0.0),
+            MuscleField::Damping => params.damping = (params.damping + change).max(0.0),
+            MuscleField::Phase => params.phase = (params.phase + change).rem_euclid(std::f32::consts::TAU),
+            MuscleField::Frequency => params.frequency = (params.frequency + change).max(0.0),
+        }
+    }
+}
+
+pub const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
+pub const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
 const PRESSED_BUTTON: Color = Color::srgb(0.35, 0.35, 0.35);
 
 fn init(
@@ -86,30 +497,639 @@ fn init(
             TextColor(Color::srgb(0.9, 0.9, 0.9)),
         ));
     });
+
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            right: Val::Px(5.0),
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(5.0)),
+            display: Display::None,
+            ..default()
+        },
+        BackgroundColor(NORMAL_BUTTON.with_alpha(0.8)),
+        PropertyPanel,
+        Editor
+    )).with_children(|parent| {
+        for field in PropertyField::all() {
+            parent.spawn((
+                Node {
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(4.0),
+                    ..default()
+                },
+                PropertyRow(field),
+            )).with_children(|row| {
+                if matches!(field, PropertyField::Muscle(MuscleField::Label)) {
+                    row.spawn((
+                        Button,
+                        Node {
+                            width: Val::Px(40.0),
+                            height: Val::Px(18.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        BackgroundColor(HOVERED_BUTTON),
+                        LabelButton,
+                    )).with_child((
+                        Text::new("Edit"),
+                        TextFont {
+                            font: font_handle.clone(),
+                            font_size: 13.0,
+                            ..default()
+                        },
+                    ));
+                } else if !field.editable() {
+                    row.spawn(Node {
+                        width: Val::Px(40.0),
+                        ..default()
+                    });
+                }
+                for (label, delta) in [("-", -1.0), ("+", 1.0)].into_iter().filter(|_| field.editable()) {
+                    row.spawn((
+                        Button,
+                        Node {
+                            width: Val::Px(18.0),
+                            height: Val::Px(18.0),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        BackgroundColor(HOVERED_BUTTON),
+                        PropertyButton { field, delta },
+                    )).with_child((
+                        Text::new(label),
+                        TextFont {
+                            font: font_handle.clone(),
+                            font_size: 13.0,
+                            ..default()
+                        },
+                    ));
+                }
+                row.spawn((
+                    Text::default(),
+                    TextFont {
+                        font: font_handle.clone(),
+                        font_size: 13.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                    PropertyText(field),
+                ));
+            });
+        }
+    });
+
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            display: Display::None,
+            ..default()
+        },
+        PromptPanel,
+        Editor
+    )).with_children(|parent| {
+        parent.spawn((
+            Node {
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.0)),
+                row_gap: Val::Px(4.0),
+                min_width: Val::Px(400.0),
+                ..default()
+            },
+            BackgroundColor(NORMAL_BUTTON.with_alpha(0.95)),
+        )).with_children(|panel| {
+            panel.spawn((
+                Text::default(),
+                TextFont {
+                    font: font_handle.clone(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                PromptText,
+            ));
+            for i in 0..super::file::MAX_RECENT_FILES {
+                panel.spawn((
+                    Button,
+                    Node {
+                        padding: UiRect::horizontal(Val::Px(4.0)),
+                        ..default()
+                    },
+                    BackgroundColor(NORMAL_BUTTON),
+                    RecentFileButton(i),
+                )).with_child((
+                    Text::default(),
+                    TextFont {
+                        font: font_handle.clone(),
+                        font_size: 13.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.7, 0.7, 0.7)),
+                    RecentFileText(i),
+                ));
+            }
+        });
+    });
+
+    commands.spawn((
+        Button,
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            left: Val::Px(5.0),
+            right: Val::Px(5.0),
+            padding: UiRect::all(Val::Px(5.0)),
+            display: Display::None,
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.5, 0.1, 0.1, 0.9)),
+        StatusPanel,
+        Editor
+    )).with_child((
+        Text::default(),
+        TextFont {
+            font: font_handle.clone(),
+            font_size: 13.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        StatusText,
+    ));
+
+    commands.spawn((
+        Text::default(),
+        TextFont {
+            font: font_handle.clone(),
+            font_size: 13.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Percent(35.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            display: Display::None,
+            ..default()
+        },
+        BackgroundColor(NORMAL_BUTTON.with_alpha(0.9)),
+        HelpPanel,
+        Editor
+    ));
+
+    commands.spawn((
+        Text::default(),
+        TextFont {
+            font: font_handle.clone(),
+            font_size: 13.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(35.0),
+            left: Val::Px(5.0),
+            max_width: Val::Percent(60.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            ..default()
+        },
+        BackgroundColor(NORMAL_BUTTON.with_alpha(0.8)),
+        ModeText,
+        Editor
+    ));
 }
 
 
-/// System to update top left coordinate/position information.
+/// System to update top left coordinate/position information. Shows the center of the selected joints when more than
+/// one is selected.
 fn update_pos_info(
     entity_selected: Res<EntitySelected>,
+    pivot: Res<super::controls::Pivot>,
+    typed: Res<super::controls::TypedValue>,
+    snapping: Res<super::snap::Snapping>,
     transform_q: Query<&Transform>,
     mut text_q: Query<&mut Text, With<PosText>>,
 ) {
     let mut text = text_q.single_mut();
 
-    match entity_selected.get() {
-        Some(joint) => {
-            let transform = transform_q.get(joint).unwrap();
+    let joints: Vec<Vec3> = entity_selected.joints()
+        .filter_map(|joint| transform_q.get(joint).ok())
+        .map(|transform| transform.translation)
+        .collect();
 
-            **text = format!(
+    let mut info = if joints.len() > 1 {
+        let center = joints.iter().sum::<Vec3>() / joints.len() as f32;
+        format!(
+            "{} joints | Center X: {:.3} | Y: {:.3} | Z: {:.3} | Pivot: {}",
+            joints.len(), center.x, center.y, center.z, pivot.name()
+        )
+    } else {
+        match entity_selected.get().and_then(|e| transform_q.get(e).ok()) {
+            Some(transform) => format!(
                 "X: {:.3} | Y: {:.3} | Z: {:.3}",
                 transform.translation.x, transform.translation.y, transform.translation.z
-            );
+            ),
+            None => String::new(),
+        }
+    };
+
+    if snapping.is_active() {
+        info.push_str(&format!(" | Snap: {} / {}°", snapping.grid, snapping.angle));
+    }
+    if let Some(value) = &typed.0 {
+        info.push_str(&format!(" | Value: {}", value));
+    }
+    **text = info;
+}
+
+/// System to show the properties of the selected joint, connector or muscle.
+fn update_property_panel(
+    structure: Res<Structure>,
+    entity_selected: Res<EntitySelected>,
+    joint_q: Query<&Joint>,
+    connector_q: Query<&Connector>,
+    muscle_q: Query<&Muscle>,
+    mut panel_q: Query<&mut Node, (With<PropertyPanel>, Without<PropertyRow>)>,
+    mut row_q: Query<(&mut Node, &PropertyRow)>,
+    mut text_q: Query<(&mut Text, &PropertyText)>,
+) {
+    let Ok(mut panel) = panel_q.get_single_mut() else {
+        return;
+    };
+
+    let selected = entity_selected.get();
+    let point = selected
+        .and_then(|e| joint_q.get(e).ok())
+        .and_then(|joint| structure.node_weight(joint.node_index));
+    let connection = selected
+        .and_then(|e| connector_q.get(e).ok())
+        .and_then(|connector| Some((
+            structure.edge_weight(connector.edge_index)?,
+            structure.edge_endpoints(connector.edge_index)?,
+        )));
+    let muscle = selected.and_then(|e| muscle_q.get(e).ok());
+
+    if point.is_none() && connection.is_none() && muscle.is_none() {
+        panel.display = Display::None;
+        return;
+    }
+    panel.display = Display::Flex;
+
+    for (mut node, row) in row_q.iter_mut() {
+        let visible = match row.0 {
+            PropertyField::Joint(_) => point.is_some(),
+            PropertyField::Connector(_) => connection.is_some(),
+            PropertyField::Muscle(_) => muscle.is_some(),
+        };
+        node.display = if visible { Display::Flex } else { Display::None };
+    }
+
+    for (mut text, field) in text_q.iter_mut() {
+        match field.0 {
+            PropertyField::Joint(f) => if let Some(point) = point {
+                **text = format!("{}: {}", f.name(), f.value(point));
+            },
+            PropertyField::Connector(f) => if let Some((connection, (j1, j2))) = connection {
+                let length = structure[j1].pos.distance(structure[j2].pos);
+                **text = format!("{}: {}", f.name(), f.value(connection, length, (j1, j2)));
+            },
+            PropertyField::Muscle(f) => if let Some(muscle) = muscle {
+                **text = format!("{}: {}", f.name(), f.value(muscle));
+            },
+        }
+    }
+}
+
+/// System to change the properties of the selected joint, connector or muscle through the property panel buttons.
+/// Presses that change nothing, such as ones at a limit, aren't recorded in the history.
+fn property_button_interact(
+    mut commands: Commands,
+    mut structure: ResMut<Structure>,
+    mut history: ResMut<History>,
+    entity_selected: Res<EntitySelected>,
+    mut joint_q: Query<(&Joint, &mut Transform), Without<Muscle>>,
+    connector_q: Query<&Connector>,
+    mut muscle_q: Query<(&mut Muscle, &Transform), Without<Joint>>,
+    interaction_q: Query<(&Interaction, &PropertyButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in interaction_q.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(selected) = entity_selected.get() else {
+            continue;
+        };
+        let before = structure.clone();
+
+        let changed = match button.field {
+            PropertyField::Joint(JointField::Parent) => {
+                let Ok((joint, _)) = joint_q.get(selected) else {
+                    continue;
+                };
+                let node = joint.node_index;
+                let options: Vec<Option<NodeIndex>> = std::iter::once(None)
+                    .chain(structure.parent_options(node).into_iter().map(Some))
+                    .collect();
+                let current = structure[node].parent;
+                let parent = cycle(&options, current, button.delta).flatten();
+                structure[node].parent = parent;
+                parent != current
+            },
+            PropertyField::Joint(field) => {
+                let Ok((joint, mut transform)) = joint_q.get_mut(selected) else {
+                    continue;
+                };
+                let Some(point) = structure.node_weight_mut(joint.node_index) else {
+                    continue;
+                };
+                let old = (point.pos, point.mass, point.radius, point.friction);
+                field.apply(point, button.delta);
+                transform.translation = point.pos;
+                transform.scale = joint_scale(point.radius);
+                old != (point.pos, point.mass, point.radius, point.friction)
+            },
+            PropertyField::Connector(ConnectorField::Length) => {
+                let Some((parent, child)) = connector_q.get(selected).ok()
+                    .and_then(|connector| structure.edge_endpoints(connector.edge_index))
+                    .map(|(j1, j2)| if structure[j2].parent == Some(j1) { (j1, j2) } else { (j2, j1) })
+                else {
+                    continue;
+                };
+
+                // the child joint is moved along the connector
+                let (p, c) = (structure[parent].pos, structure[child].pos);
+                let min = structure[parent].radius + structure[child].radius;
+                let length = (c.distance(p) + button.delta * ConnectorField::Length.step()).max(min);
+                let pos = p + (c - p).normalize_or(Vec3::Y) * length;
+                structure[child].pos = pos;
+                if let Some(Ok((_, mut transform))) = structure[child].entityid.map(|e| joint_q.get_mut(e)) {
+                    transform.translation = pos;
+                }
+                pos != c
+            },
+            PropertyField::Connector(field) => {
+                let Ok(connector) = connector_q.get(selected) else {
+                    continue;
+                };
+                let Some((j1, j2)) = structure.edge_endpoints(connector.edge_index) else {
+                    continue;
+                };
+                let length = structure[j1].pos.distance(structure[j2].pos);
+                let connection = &mut structure[connector.edge_index];
+                let old = (connection.joint.clone(), connection.limits.clone());
+                field.apply(connection, button.delta, length);
+                old != (connection.joint.clone(), connection.limits.clone())
+            },
+            PropertyField::Muscle(field @ (MuscleField::Anchor1 | MuscleField::Anchor2)) => {
+                let Ok((muscle, _)) = muscle_q.get(selected) else {
+                    continue;
+                };
+                let (Some(anchor1), Some(anchor2)) = (muscle.anchor1, muscle.anchor2) else {
+                    continue;
+                };
+
+                // the other anchor stays, the muscle and its entity are moved to the next connector next to it
+                let (kept, moved) = match field {
+                    MuscleField::Anchor1 => (anchor2, anchor1),
+                    _ => (anchor1, anchor2),
+                };
+                let Some(to) = cycle(&structure.anchor_options(kept, moved), moved, button.delta) else {
+                    continue;
+                };
+                if !structure.move_muscle(kept, moved, to) {
+                    continue;
+                }
+                let ids = (structure[kept].id, structure[to].id);
+                commands.trigger(super::RespawnEvent);
+                commands.trigger(super::watch::ReselectEvent(vec![super::watch::SelectedId::Muscle(ids.0, ids.1)]));
+                true
+            },
+            PropertyField::Muscle(field) => {
+                let Ok((mut muscle, transform)) = muscle_q.get_mut(selected) else {
+                    continue;
+                };
+                let (Some(anchor1), Some(anchor2)) = (muscle.anchor1, muscle.anchor2) else {
+                    continue;
+                };
+
+                // muscles are scaled along y to half their length
+                let old = muscle.params.clone();
+                field.apply(&mut muscle.params, button.delta, transform.scale.y * 2.0);
+                structure.set_muscle_params(anchor1, anchor2, muscle.params.clone());
+                old != muscle.params
+            },
+        };
+
+        if changed {
+            history.record(&before);
+        }
+    }
+}
+
+/// System to open the label prompt of the selected muscle when its label button is clicked.
+fn label_button_interact(
+    mut prompt: ResMut<PathPrompt>,
+    entity_selected: Res<EntitySelected>,
+    muscle_q: Query<&Muscle>,
+    interaction_q: Query<&Interaction, (Changed<Interaction>, With<LabelButton>)>,
+) {
+    for interaction in interaction_q.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some((selected, muscle)) = entity_selected.get().and_then(|e| Some((e, muscle_q.get(e).ok()?))) else {
+            continue;
+        };
+        prompt.open(PromptKind::MuscleLabel(selected), muscle.params.label.clone().unwrap_or_default());
+    }
+}
+
+/// System to show the path prompt and the recent files while it is open.
+fn update_path_prompt(
+    prompt: Res<PathPrompt>,
+    recent: Res<RecentFiles>,
+    mut panel_q: Query<&mut Node, (With<PromptPanel>, Without<RecentFileButton>)>,
+    mut button_q: Query<(&mut Node, &RecentFileButton)>,
+    mut prompt_text_q: Query<&mut Text, (With<PromptText>, Without<RecentFileText>)>,
+    mut recent_text_q: Query<(&mut Text, &RecentFileText)>,
+) {
+    if !prompt.is_changed() && !recent.is_changed() {
+        return;
+    }
+    let Ok(mut panel) = panel_q.get_single_mut() else {
+        return;
+    };
+    let Some(current) = &prompt.0 else {
+        panel.display = Display::None;
+        return;
+    };
+    panel.display = Display::Flex;
+
+    let title = match current.kind {
+        PromptKind::Open => "Open",
+        PromptKind::SaveAs => "Save as",
+        PromptKind::MuscleLabel(_) => "Muscle label",
+    };
+    for mut text in prompt_text_q.iter_mut() {
+        **text = format!("{}: {}_", title, current.text);
+    }
+    // recent files are only offered for paths
+    let recent_count = match current.kind {
+        PromptKind::MuscleLabel(_) => 0,
+        _ => recent.0.len(),
+    };
+    for (mut node, button) in button_q.iter_mut() {
+        node.display = if button.0 < recent_count { Display::Flex } else { Display::None };
+    }
+    for (mut text, file) in recent_text_q.iter_mut() {
+        if let Some(path) = recent.0.get(file.0) {
+            **text = path.display().to_string();
+        }
+    }
+}
+
+/// System to fill the path prompt with a recent file when clicked.
+fn recent_file_interact(
+    mut prompt: ResMut<PathPrompt>,
+    recent: Res<RecentFiles>,
+    interaction_q: Query<(&Interaction, &RecentFileButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in interaction_q.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let (Some(current), Some(path)) = (prompt.0.as_mut(), recent.0.get(button.0)) else {
+            continue;
+        };
+        current.text = path.display().to_string();
+    }
+}
+
+/// System to show the status message.
+fn update_status_message(
+    message: Res<StatusMessage>,
+    mut panel_q: Query<&mut Node, With<StatusPanel>>,
+    mut text_q: Query<&mut Text, With<StatusText>>,
+) {
+    if !message.is_changed() {
+        return;
+    }
+    let Ok(mut panel) = panel_q.get_single_mut() else {
+        return;
+    };
+    match &message.0 {
+        Some(msg) => {
+            panel.display = Display::Flex;
+            for mut text in text_q.iter_mut() {
+                **text = format!("{}  [click to dismiss]", msg);
+            }
+        },
+        None => panel.display = Display::None,
+    }
+}
+
+/// System to show the key bindings while the key help is toggled on.
+fn update_key_help(
+    help: Res<KeyHelp>,
+    controls: Res<EditorControls>,
+    mut help_q: Query<(&mut Text, &mut Node, Ref<HelpPanel>)>,
+) {
+    let Ok((mut text, mut node, panel)) = help_q.get_single_mut() else {
+        return;
+    };
+    if !help.is_changed() && !controls.is_changed() && !panel.is_added() {
+        return;
+    }
+    node.display = if help.0 { Display::Flex } else { Display::None };
+
+    let mut lines: Vec<String> = controls.bindings.iter()
+        .map(|binding| format!("{:<14} {}", binding.key_name(), binding.action.name()))
+        .collect();
+    if let Some(button) = controls.confirm_button {
+        lines.push(format!("{:<14} {}", format!("{:?} click", button), ActionEvent::Confirm.name()));
+    }
+    if let Some(key) = controls.snap_invert_key {
+        lines.push(format!("{:<14} {}", format!("{} (hold)", key_name(key)), "Invert snapping"));
+    }
+    **text = lines.join("\n");
+}
+
+/// System to show the current edit mode, its locked axis and target, along with the keys accepted in it.
+fn update_mode_hud(
+    edit_mode: Res<EditMode>,
+    controls: Res<EditorControls>,
+    entity_selected: Res<EntitySelected>,
+    joint_q: Query<&Joint>,
+    connector_q: Query<&Connector>,
+    mut text_q: Query<(&mut Text, Ref<ModeText>)>,
+) {
+    let Ok((mut text, hud)) = text_q.get_single_mut() else {
+        return;
+    };
+    if !edit_mode.is_changed() && !controls.is_changed() && !entity_selected.is_changed() && !hud.is_added() {
+        return;
+    }
+
+    let describe = |e: Entity| {
+        joint_q.get(e).map(|joint| format!("Joint {}", joint.node_index.index()))
+            .or_else(|_| connector_q.get(e).map(|connector| format!("Connector {}", connector.edge_index.index())))
+            .ok()
+    };
+
+    let mut status = format!("Mode: {}", edit_mode.name());
+    if let Some(axis) = edit_mode.axis() {
+        status.push_str(&format!(" | Axis: {:?}", axis));
+    }
+    match edit_mode.target() {
+        Some(target) => if let Some(name) = describe(target) {
+            status.push_str(&format!(" | {}", name));
         },
-        None => {
-            **text = "".to_string();
+        None => if let Some(name) = entity_selected.get().and_then(describe) {
+            status.push_str(&format!(" | Selected: {}", name));
+            if entity_selected.len() > 1 {
+                status.push_str(&format!(" (+{} more)", entity_selected.len() - 1));
+            }
         },
-     }
+    }
+
+    let mut hints = Vec::new();
+    let mut axis_keys = Vec::new();
+    for action in edit_mode.accepted_actions(&entity_selected) {
+        let Some(keys) = controls.keys_for(action) else {
+            continue;
+        };
+        match action {
+            ActionEvent::AxisChange(_) => axis_keys.push(keys),
+            _ => hints.push(format!("[{}] {}", keys, action.name())),
+        }
+    }
+    if !axis_keys.is_empty() {
+        hints.push(format!("[{}] Lock axis", axis_keys.join("/")));
+    }
+    if edit_mode.takes_typed_value() {
+        hints.push("[0-9] Type value".to_string());
+    }
+
+    **text = format!("{}\n{}", status, hints.join("  "));
+}
+
+/// System to dismiss the status message when clicked.
+fn status_message_interact(
+    mut message: ResMut<StatusMessage>,
+    interaction_q: Query<&Interaction, (Changed<Interaction>, With<StatusPanel>)>,
+) {
+    for interaction in interaction_q.iter() {
+        if *interaction == Interaction::Pressed {
+            message.0 = None;
+        }
+    }
 }
 
 fn tbutton_interact(
@@ -0,0 +1,177 @@
+use std::{path::{Path, PathBuf}, time::SystemTime};
+
+use bevy::prelude::*;
+use petgraph::graph::EdgeIndex;
+
+use super::{
+    file::{read_structure, report_issues, CurrentFile, LoadFailed},
+    history::History,
+    ui::StatusMessage,
+    RespawnEvent,
+};
+use crate::{
+    selection::{EntitySelected, SelectableEntity, SelectionUpdateEvent},
+    format::Metadata,
+    structure::{Connector, Joint, Muscle, Structure},
+};
+
+/// Seconds between checks of the current file for changes.
+pub const WATCH_INTERVAL: f32 = 1.0;
+
+/// Modification time of the current file as last loaded or saved by the editor. A newer one means the file was
+/// changed by something else.
+#[derive(Resource, Default)]
+pub struct FileWatch {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl FileWatch {
+    /// Takes the current modification time of `path` as known, so it isn't reloaded.
+    pub fn sync(&mut self, path: &Path) {
+        self.path = path.to_path_buf();
+        self.modified = modified(path);
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Reloads the structure from the current file, keeping the selection where its ids still exist.
+#[derive(Event)]
+pub struct ReloadEvent;
+
+/// Selects joints, connectors and muscles by their ids once the entities are respawned, replacing the selection.
+#[derive(Event)]
+pub struct ReselectEvent(pub Vec<SelectedId>);
+
+pub enum SelectedId {
+    Joint(u32),
+    Connector(u32),
+    /// Muscle by the ids of its anchors.
+    Muscle(u32, u32),
+}
+
+/// System that checks the current file every `WATCH_INTERVAL` seconds and reloads it when it changed on disk.
+pub fn watch_file(
+    mut commands: Commands,
+    time: Res<Time>,
+    current_file: Res<CurrentFile>,
+    mut watch: ResMut<FileWatch>,
+    mut elapsed: Local<f32>,
+) {
+    // a file that was just opened or saved under a new name is taken as is
+    if watch.path != current_file.0 {
+        watch.sync(&current_file.0);
+        return;
+    }
+
+    *elapsed += time.delta_secs();
+    if *elapsed < WATCH_INTERVAL {
+        return;
+    }
+    *elapsed = 0.0;
+
+    let modified = modified(&current_file.0);
+    // a file that was removed is kept in the editor until it is saved again
+    if modified.is_none() || modified == watch.modified {
+        return;
+    }
+    watch.modified = modified;
+    commands.trigger(ReloadEvent);
+}
+
+/// Replaces the structure with the current file. The replaced structure is recorded in the history so the reload can
+/// be undone, which is pointed out when it had unsaved changes. Triggers with ReloadEvent.
+pub fn reload(
+    _: Trigger<ReloadEvent>,
+    mut commands: Commands,
+    mut structure: ResMut<Structure>,
+    mut metadata: ResMut<Metadata>,
+    mut history: ResMut<History>,
+    mut message: ResMut<StatusMessage>,
+    current_file: Res<CurrentFile>,
+    mut load_failed: ResMut<LoadFailed>,
+    entity_selected: Res<EntitySelected>,
+    joint_q: Query<&Joint>,
+    connector_q: Query<&Connector>,
+    muscle_q: Query<&Muscle>,
+) {
+    let path = &current_file.0;
+    let loaded = match read_structure(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            // the file may be read while it's still being written, the next change reloads it
+            error!("{}", e);
+            message.set(e.to_string());
+            return;
+        },
+    };
+
+    let edge_id = |edge: Option<EdgeIndex>| structure.edge_weight(edge?).map(|connection| connection.id);
+    let selected = entity_selected.iter()
+        .filter_map(|selected| match *selected {
+            SelectableEntity::Joint(e) => joint_q.get(e).ok()
+                .and_then(|joint| structure.node_weight(joint.node_index))
+                .map(|point| SelectedId::Joint(point.id)),
+            SelectableEntity::Connector(e) => connector_q.get(e).ok()
+                .and_then(|connector| edge_id(Some(connector.edge_index)))
+                .map(SelectedId::Connector),
+            SelectableEntity::Muscle(e) => muscle_q.get(e).ok()
+                .and_then(|muscle| Some(SelectedId::Muscle(edge_id(muscle.anchor1)?, edge_id(muscle.anchor2)?))),
+        })
+        .collect();
+
+    let replaced_changes = history.is_dirty();
+    history.cancel();
+    history.record(&structure);
+    // the structure matches the file again
+    history.mark_saved();
+    *structure = loaded.structure;
+    *metadata = loaded.metadata;
+    load_failed.0 = false;
+    // the issues of the file, if any, are listed below the note
+    if replaced_changes {
+        message.0 = None;
+    }
+    report_issues(&structure, path, &mut message);
+    if replaced_changes {
+        let note = format!("{} changed on disk and replaced the unsaved changes, undo to restore them", path.display());
+        warn!("{}", note);
+        message.0 = Some(match message.0.take() {
+            Some(issues) => format!("{}\n{}", note, issues),
+            None => note,
+        });
+    }
+    commands.trigger(RespawnEvent);
+    commands.trigger(ReselectEvent(selected));
+    info!(":: Reloaded {}", path.display());
+}
+
+/// Selects the entities with the given ids in the respawned structure, skipping the ones that no longer exist.
+pub fn reselect(
+    trigger: Trigger<ReselectEvent>,
+    mut commands: Commands,
+    structure: Res<Structure>,
+    mut entity_selected: ResMut<EntitySelected>,
+) {
+    for selected in trigger.event().0.iter() {
+        let entity = match *selected {
+            SelectedId::Joint(id) => structure.node_by_id(id)
+                .and_then(|node| structure[node].entityid)
+                .map(SelectableEntity::Joint),
+            SelectedId::Connector(id) => structure.edge_by_id(id)
+                .and_then(|edge| structure[edge].entityid)
+                .map(SelectableEntity::Connector),
+            SelectedId::Muscle(id, other) => structure.edge_by_id(id)
+                .zip(structure.edge_by_id(other))
+                .and_then(|(edge, other)| structure[edge].muscles.get(&other).copied())
+                .map(SelectableEntity::Muscle),
+        };
+        if let Some(entity) = entity {
+            entity_selected.add(entity);
+        }
+    }
+    commands.trigger(SelectionUpdateEvent);
+}
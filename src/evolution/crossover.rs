@@ -0,0 +1,267 @@
+//! Crossover operators combining two structures into a child.
+//!
+//! Children are always valid structures: connected, with muscles only between existing edges and recorded on both
+//! of them, and parents pointing to neighbouring joints.
+
+use bevy::{prelude::*, utils::HashMap};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use rand::Rng;
+
+use crate::structure::*;
+
+use super::mutation::muscle_pairs;
+
+/// Combines two parents into a child with a randomly chosen operator.
+pub fn crossover<R: Rng>(parent1: &Structure, parent2: &Structure, rng: &mut R) -> Structure {
+    if rng.gen_bool(0.5) {
+        plane_crossover(parent1, parent2, rng)
+    } else {
+        subtree_crossover(parent1, parent2, rng).unwrap_or_else(|| parent1.clone())
+    }
+}
+
+/// Splits both parents by a random plane through the midpoint of their centroids. The child takes the joints of
+/// `parent1` in front of the plane and the joints of `parent2` behind it, and the two halves are stitched together
+/// at their closest joints.
+pub fn plane_crossover<R: Rng>(parent1: &Structure, parent2: &Structure, rng: &mut R) -> Structure {
+    let normal = loop {
+        let v = Vec3::new(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0));
+        if v.length_squared() > 1e-4 {
+            break v.normalize();
+        }
+    };
+    let origin = (centroid(parent1) + centroid(parent2)) / 2.0;
+
+    let front: Vec<NodeIndex> = parent1.node_indices()
+        .filter(|n| (parent1[*n].pos - origin).dot(normal) >= 0.0)
+        .collect();
+    let back: Vec<NodeIndex> = parent2.node_indices()
+        .filter(|n| (parent2[*n].pos - origin).dot(normal) < 0.0)
+        .collect();
+
+    if front.is_empty() {
+        return parent2.clone();
+    }
+    if back.is_empty() {
+        return parent1.clone();
+    }
+
+    let mut child = Structure::default();
+    copy_subgraph(parent1, &front, &mut child, Vec3::ZERO);
+    copy_subgraph(parent2, &back, &mut child, Vec3::ZERO);
+    connect_components(&mut child);
+    child
+}
+
+/// Replaces a random subtree of `parent1` (a joint and all joints whose parent chain leads to it) with a random
+/// subtree of `parent2`. The new subtree is moved so its root takes the place of the old one and is attached to the
+/// old root's parent. Returns None if `parent1` has no joint with a parent, or if the old root's parent is missing or
+/// part of the replaced subtree, which only happens with malformed parents.
+pub fn subtree_crossover<R: Rng>(parent1: &Structure, parent2: &Structure, rng: &mut R) -> Option<Structure> {
+    let roots1: Vec<NodeIndex> = parent1.node_indices()
+        .filter(|n| parent1[*n].parent.is_some())
+        .collect();
+    let roots2: Vec<NodeIndex> = parent2.node_indices().collect();
+    if roots1.is_empty() || roots2.is_empty() {
+        return None;
+    }
+    let root1 = roots1[rng.gen_range(0..roots1.len())];
+    let root2 = roots2[rng.gen_range(0..roots2.len())];
+
+    let attach = parent1[root1].parent?;
+    let offset = parent1[root1].pos - parent2[root2].pos;
+
+    let mut child = parent1.clone();
+    for node in subtree(parent1, root1) {
+        child.remove_point(node);
+    }
+    if !child.contains_node(attach) {
+        return None;
+    }
+
+    let node_map = copy_subgraph(parent2, &subtree(parent2, root2), &mut child, offset);
+    let new_root = node_map[&root2];
+    child.add_connection(new_root, attach, Connection::default());
+    child[new_root].parent = Some(attach);
+
+    connect_components(&mut child);
+    Some(child)
+}
+
+/// `root` and every joint whose parent chain leads to it.
+pub fn subtree(structure: &Structure, root: NodeIndex) -> Vec<NodeIndex> {
+    let mut children: HashMap<NodeIndex, Vec<NodeIndex>> = HashMap::new();
+    for node in structure.node_indices() {
+        if let Some(parent) = structure[node].parent {
+            children.entry(parent).or_default().push(node);
+        }
+    }
+
+    let mut nodes = vec![root];
+    let mut i = 0;
+    while i < nodes.len() {
+        if let Some(c) = children.get(&nodes[i]) {
+            for child in c {
+                // guards against parent cycles in malformed structures
+                if !nodes.contains(child) {
+                    nodes.push(*child);
+                }
+            }
+        }
+        i += 1;
+    }
+    nodes
+}
+
+/// Copies the given joints of `src` into `dst`, moved by `offset`, along with the connectors and muscles between
+/// them, keeping the joint types and limits of the connectors. Parents outside of the copied joints are cleared. The
+/// copies get new ids in `dst`. Returns the map from `src` to `dst` node indices.
+fn copy_subgraph(
+    src: &Structure,
+    nodes: &[NodeIndex],
+    dst: &mut Structure,
+    offset: Vec3,
+) -> HashMap<NodeIndex, NodeIndex> {
+    let mut node_map = HashMap::new();
+    for node in nodes {
+        let new = dst.add_point(Point {
+            entityid: None,
+            pos: src[*node].pos + offset,
+            parent: None,
+            ..src[*node].clone()
+        });
+        node_map.insert(*node, new);
+    }
+
+    for node in nodes {
+        let parent = src[*node].parent
+            .filter(|p| src.contains_edge(*node, *p))
+            .and_then(|p| node_map.get(&p).copied());
+        dst[node_map[node]].parent = parent;
+    }
+
+    let mut edge_map: HashMap<EdgeIndex, EdgeIndex> = HashMap::new();
+    for edge in src.edge_indices() {
+        let (a, b) = src.edge_endpoints(edge).unwrap();
+        let (Some(&new_a), Some(&new_b)) = (node_map.get(&a), node_map.get(&b)) else {
+            continue;
+        };
+        let new = dst.add_connection(new_a, new_b, Connection {
+            entityid: None,
+            muscles: default(),
+            muscle_data: default(),
+            ..src[edge].clone()
+        });
+        edge_map.insert(edge, new);
+    }
+
+    for (a, b) in muscle_pairs(src) {
+        if let (Some(&new_a), Some(&new_b)) = (edge_map.get(&a), edge_map.get(&b)) {
+            dst.add_muscle_data(new_a, new_b, src[a].muscle_data[&b].clone());
+        }
+    }
+
+    node_map
+}
+
+/// Connects every component of the structure to the first one through a new connector between their closest joints.
+fn connect_components(structure: &mut Structure) {
+    loop {
+        let Some(start) = structure.node_indices().next() else {
+            return;
+        };
+        let mut main = Vec::new();
+        let mut dfs = petgraph::visit::Dfs::new(&structure.0, start);
+        while let Some(n) = dfs.next(&structure.0) {
+            main.push(n);
+        }
+        if main.len() == structure.node_count() {
+            return;
+        }
+
+        let mut closest: Option<(f32, NodeIndex, NodeIndex)> = None;
+        for other in structure.node_indices().filter(|n| !main.contains(n)) {
+            for node in main.iter() {
+                let dist = structure[*node].pos.distance_squared(structure[other].pos);
+                if closest.is_none_or(|(d, _, _)| dist < d) {
+                    closest = Some((dist, *node, other));
+                }
+            }
+        }
+        let (_, a, b) = closest.unwrap();
+        structure.add_connection(a, b, Connection::default());
+    }
+}
+
+/// Average position of the joints of the structure.
+fn centroid(structure: &Structure) -> Vec3 {
+    let count = structure.node_count();
+    if count == 0 {
+        return Vec3::ZERO;
+    }
+    structure.node_indices().map(|n| structure[n].pos).sum::<Vec3>() / count as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::evolution::mutation::{add_joint, add_muscle};
+
+    /// Number of children bred by each test.
+    const CHILDREN: usize = 50;
+
+    fn parent(rng: &mut ChaCha8Rng, joints: usize) -> Structure {
+        let mut structure = Structure::with_root();
+        for _ in 0..joints {
+            add_joint(&mut structure, rng);
+        }
+        for _ in 0..joints / 2 {
+            add_muscle(&mut structure, rng);
+        }
+        structure
+    }
+
+    /// Asserts the child is in one piece and every muscle is recorded on both of its existing edges.
+    fn assert_valid(child: &Structure) {
+        assert_eq!(child.component_count(), 1);
+        for edge in child.edge_indices() {
+            for (other, params) in child[edge].muscle_data.iter() {
+                assert_eq!(child.muscle_params(*other, edge), Some(params));
+            }
+        }
+    }
+
+    #[test]
+    fn plane_crossover_is_valid() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        for _ in 0..CHILDREN {
+            let (parent1, parent2) = (parent(&mut rng, 8), parent(&mut rng, 5));
+            assert_valid(&plane_crossover(&parent1, &parent2, &mut rng));
+        }
+    }
+
+    #[test]
+    fn subtree_crossover_is_valid() {
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        for _ in 0..CHILDREN {
+            let (parent1, parent2) = (parent(&mut rng, 8), parent(&mut rng, 5));
+            let child = subtree_crossover(&parent1, &parent2, &mut rng).unwrap();
+            assert_valid(&child);
+        }
+    }
+
+    #[test]
+    fn subtree_crossover_rejects_parent_cycle() {
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let mut parent1 = Structure::with_root();
+        let root = parent1.node_indices().next().unwrap();
+        let node = add_joint(&mut parent1, &mut rng).unwrap();
+        parent1[root].parent = Some(node);
+
+        let parent2 = parent(&mut rng, 3);
+        assert!(subtree_crossover(&parent1, &parent2, &mut rng).is_none());
+    }
+}
@@ -0,0 +1,210 @@
+//! Headless evolution of structures.
+//!
+//! A population is bred from a seed structure, every individual is simulated with [`Simulation`] and scored with
+//! [`fitness`], and the next generation is produced through tournament selection, crossover and mutation. All
+//! randomness comes from a single seeded RNG so a run can be reproduced from its config.
+
+pub mod crossover;
+pub mod mutation;
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{physics::{SimConfig, Simulation}, structure::Structure};
+
+/// Parameters of an evolution run.
+#[derive(Debug, Clone, Copy)]
+pub struct EvolutionConfig {
+    /// Number of individuals in each generation.
+    pub population: usize,
+    /// Number of generations to run.
+    pub generations: usize,
+    /// Simulated seconds per individual.
+    pub sim_seconds: f32,
+    /// Number of best individuals copied unchanged to the next generation.
+    pub elitism: usize,
+    /// Number of individuals competing in each tournament selection.
+    pub tournament_size: usize,
+    /// Chance of an offspring being bred from two parents instead of one.
+    pub crossover_rate: f32,
+    /// Seed of the random number generator.
+    pub seed: u64,
+    pub sim: SimConfig,
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        EvolutionConfig {
+            population: 20,
+            generations: 10,
+            sim_seconds: 10.0,
+            elitism: 2,
+            tournament_size: 3,
+            crossover_rate: 0.5,
+            seed: 0,
+            sim: SimConfig::default(),
+        }
+    }
+}
+
+/// A structure of the population with its fitness, if it has been evaluated.
+#[derive(Debug, Clone)]
+pub struct Individual {
+    pub structure: Structure,
+    pub fitness: Option<f32>,
+}
+
+/// Fitness statistics of a single generation.
+#[derive(Debug, Clone, Copy)]
+pub struct GenerationReport {
+    pub generation: usize,
+    pub best: f32,
+    pub mean: f32,
+    pub worst: f32,
+}
+
+impl std::fmt::Display for GenerationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Generation {}: best {:.3} | mean {:.3} | worst {:.3}",
+            self.generation, self.best, self.mean, self.worst
+        )
+    }
+}
+
+pub struct Evolution {
+    pub config: EvolutionConfig,
+    pub population: Vec<Individual>,
+    generation: usize,
+    best: Option<Individual>,
+    rng: ChaCha8Rng,
+}
+
+impl Evolution {
+    /// Creates the first generation from mutated copies of the seed structure. The seed itself is kept as the
+    /// first individual.
+    pub fn new(seed: &Structure, config: EvolutionConfig) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+        let mut population = vec![Individual { structure: seed.clone(), fitness: None }];
+        while population.len() < config.population.max(1) {
+            let mut structure = seed.clone();
+            mutation::mutate(&mut structure, &mut rng);
+            population.push(Individual { structure, fitness: None });
+        }
+
+        Evolution {
+            config,
+            population,
+            generation: 0,
+            best: None,
+            rng,
+        }
+    }
+
+    /// Whether the configured number of generations has been evaluated.
+    pub fn is_finished(&self) -> bool {
+        self.generation >= self.config.generations
+    }
+
+    /// Best individual evaluated so far across all generations.
+    pub fn best(&self) -> Option<&Individual> {
+        self.best.as_ref()
+    }
+
+    /// Evaluates the next individual of the current generation that has no fitness yet. Returns false once the whole
+    /// generation is evaluated.
+    pub fn evaluate_next(&mut self) -> bool {
+        let Some(individual) = self.population.iter_mut().find(|i| i.fitness.is_none()) else {
+            return false;
+        };
+        individual.fitness = Some(fitness(&individual.structure, self.config.sim, self.config.sim_seconds));
+        true
+    }
+
+    /// Evaluates the rest of the current generation and replaces it with the next one.
+    pub fn step(&mut self) -> GenerationReport {
+        while self.evaluate_next() {}
+        self.population.sort_by(|a, b| b.fitness.unwrap().total_cmp(&a.fitness.unwrap()));
+
+        let scores: Vec<f32> = self.population.iter().map(|i| i.fitness.unwrap()).collect();
+        let report = GenerationReport {
+            generation: self.generation,
+            best: scores[0],
+            mean: scores.iter().sum::<f32>() / scores.len() as f32,
+            worst: scores[scores.len() - 1],
+        };
+
+        if self.best.as_ref().is_none_or(|best| best.fitness < self.population[0].fitness) {
+            self.best = Some(self.population[0].clone());
+        }
+
+        self.population = self.breed();
+        self.generation += 1;
+        report
+    }
+
+    /// Runs all remaining generations, calling `on_report` after each one.
+    pub fn run(&mut self, mut on_report: impl FnMut(&GenerationReport)) {
+        while !self.is_finished() {
+            let report = self.step();
+            on_report(&report);
+        }
+    }
+
+    /// Produces the next generation from the current sorted and evaluated one.
+    fn breed(&mut self) -> Vec<Individual> {
+        let mut next: Vec<Individual> = self.population.iter()
+            .take(self.config.elitism)
+            .cloned()
+            .collect();
+
+        while next.len() < self.population.len() {
+            let parent1 = self.select();
+            let mut structure = if self.rng.gen::<f32>() < self.config.crossover_rate {
+                let parent2 = self.select();
+                crossover::crossover(
+                    &self.population[parent1].structure,
+                    &self.population[parent2].structure,
+                    &mut self.rng
+                )
+            } else {
+                self.population[parent1].structure.clone()
+            };
+            mutation::mutate(&mut structure, &mut self.rng);
+            next.push(Individual { structure, fitness: None });
+        }
+        next
+    }
+
+    /// Tournament selection. Returns the index of the winner in the population.
+    fn select(&mut self) -> usize {
+        let mut winner = self.rng.gen_range(0..self.population.len());
+        for _ in 1..self.config.tournament_size {
+            let contender = self.rng.gen_range(0..self.population.len());
+            // population is sorted, lower index is fitter
+            winner = winner.min(contender);
+        }
+        winner
+    }
+}
+
+/// Horizontal distance travelled by the center of mass of the structure after `seconds` of simulation. Structures
+/// that blow up the simulation score zero.
+pub fn fitness(structure: &Structure, config: SimConfig, seconds: f32) -> f32 {
+    if structure.node_count() == 0 {
+        return 0.0;
+    }
+    let mut sim = Simulation::new(structure, config);
+    let start = sim.center_of_mass();
+    sim.run(seconds);
+    let travelled = (sim.center_of_mass() - start) * Vec3::new(1.0, 0.0, 1.0);
+
+    let distance = travelled.length();
+    if distance.is_finite() {
+        distance
+    } else {
+        0.0
+    }
+}
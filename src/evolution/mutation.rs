@@ -0,0 +1,352 @@
+//! Mutation operators over structures.
+//!
+//! Every operator keeps the invariants the editor maintains: muscles are recorded in the `muscle_data` of both
+//! of their edges, and a joint's parent is always one of its neighbours. Operators only touch the serialized data,
+//! so they are meant for structures without spawned entities.
+
+use bevy::prelude::*;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use rand::Rng;
+
+use crate::structure::*;
+
+/// Maximum distance a joint is moved along each axis by a jitter.
+const JITTER: f32 = 0.5;
+/// Distance of a new joint from the surface of the joint it hangs off. Same as the default extension of the editor.
+const JOINT_EXTENSION: f32 = 2.0;
+/// Number of times `mutate` looks for an applicable operator before giving up.
+const MUTATE_ATTEMPTS: usize = 8;
+
+/// A single kind of mutation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+    JitterJoint,
+    AddJoint,
+    RemoveJoint,
+    AddConnector,
+    RemoveConnector,
+    AddMuscle,
+    RemoveMuscle,
+    TweakMuscle,
+    TweakJoint,
+    Reparent,
+}
+
+impl Mutation {
+    pub const ALL: [Mutation; 10] = [
+        Mutation::JitterJoint,
+        Mutation::AddJoint,
+        Mutation::RemoveJoint,
+        Mutation::AddConnector,
+        Mutation::RemoveConnector,
+        Mutation::AddMuscle,
+        Mutation::RemoveMuscle,
+        Mutation::TweakMuscle,
+        Mutation::TweakJoint,
+        Mutation::Reparent,
+    ];
+
+    /// Relative chance of the mutation being picked by `mutate`.
+    pub fn weight(self) -> u32 {
+        match self {
+            Mutation::JitterJoint | Mutation::TweakMuscle => 6,
+            Mutation::AddMuscle | Mutation::RemoveMuscle | Mutation::TweakJoint => 2,
+            _ => 1,
+        }
+    }
+
+    /// Applies the mutation. Returns false if it could not be applied to this structure.
+    pub fn apply<R: Rng>(self, structure: &mut Structure, rng: &mut R) -> bool {
+        match self {
+            Mutation::JitterJoint => jitter_joint(structure, rng, JITTER).is_some(),
+            Mutation::AddJoint => add_joint(structure, rng).is_some(),
+            Mutation::RemoveJoint => remove_leaf_joint(structure, rng).is_some(),
+            Mutation::AddConnector => add_connector(structure, rng).is_some(),
+            Mutation::RemoveConnector => remove_connector(structure, rng).is_some(),
+            Mutation::AddMuscle => add_muscle(structure, rng).is_some(),
+            Mutation::RemoveMuscle => remove_muscle(structure, rng).is_some(),
+            Mutation::TweakMuscle => tweak_muscle(structure, rng).is_some(),
+            Mutation::TweakJoint => tweak_joint(structure, rng).is_some(),
+            Mutation::Reparent => reparent(structure, rng).is_some(),
+        }
+    }
+}
+
+/// Applies a random applicable mutation to the structure, picked according to `Mutation::weight`.
+pub fn mutate<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<Mutation> {
+    let total: u32 = Mutation::ALL.iter().map(|m| m.weight()).sum();
+    for _ in 0..MUTATE_ATTEMPTS {
+        let mut roll = rng.gen_range(0..total);
+        let mutation = *Mutation::ALL.iter()
+            .find(|m| {
+                if roll < m.weight() {
+                    return true;
+                }
+                roll -= m.weight();
+                false
+            })
+            .unwrap();
+
+        if mutation.apply(structure, rng) {
+            return Some(mutation);
+        }
+    }
+    None
+}
+
+/// Moves a random joint by up to `amount` along each axis. Returns the joint moved.
+pub fn jitter_joint<R: Rng>(structure: &mut Structure, rng: &mut R, amount: f32) -> Option<NodeIndex> {
+    let node = random_node(structure, rng)?;
+    let offset = Vec3::new(
+        rng.gen_range(-amount..=amount),
+        rng.gen_range(-amount..=amount),
+        rng.gen_range(-amount..=amount),
+    );
+    structure[node].pos += offset;
+    Some(node)
+}
+
+/// Randomly scales the mass, radius and friction of a random joint.
+pub fn tweak_joint<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<NodeIndex> {
+    let node = random_node(structure, rng)?;
+    let point = &mut structure[node];
+    point.mass = (point.mass * rng.gen_range(0.8..=1.25)).clamp(0.1, 10.0);
+    point.radius = (point.radius * rng.gen_range(0.8..=1.25)).clamp(0.2, 3.0);
+    point.friction = (point.friction * rng.gen_range(0.8..=1.25)).clamp(0.0, 10.0);
+    Some(node)
+}
+
+/// Adds a joint connected to a random existing joint, which becomes its parent. Returns the new joint.
+pub fn add_joint<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<NodeIndex> {
+    let parent = random_node(structure, rng)?;
+    let dir = random_direction(rng);
+    let pos = structure[parent].pos + dir * (structure[parent].radius + JOINT_EXTENSION);
+
+    let node = structure.add_point(Point {
+        pos,
+        parent: Some(parent),
+        ..default()
+    });
+    structure.add_connection(node, parent, Connection::default());
+    Some(node)
+}
+
+/// Removes a random joint with a single connector, along with the connector and its muscles. The last joint is never
+/// removed. Returns the removed joint's former index.
+pub fn remove_leaf_joint<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<NodeIndex> {
+    if structure.node_count() <= 1 {
+        return None;
+    }
+    let leaves: Vec<NodeIndex> = structure.node_indices()
+        .filter(|n| structure.edges(*n).count() == 1)
+        .collect();
+    let node = pick(&leaves, rng)?;
+    structure.remove_point(node);
+    Some(node)
+}
+
+/// Adds a connector between two random joints that aren't connected yet.
+pub fn add_connector<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<EdgeIndex> {
+    let nodes: Vec<NodeIndex> = structure.node_indices().collect();
+    let mut pairs = Vec::new();
+    for (i, a) in nodes.iter().enumerate() {
+        for b in nodes.iter().skip(i + 1) {
+            if !structure.contains_edge(*a, *b) {
+                pairs.push((*a, *b));
+            }
+        }
+    }
+    let (a, b) = pick(&pairs, rng)?;
+    Some(structure.add_connection(a, b, Connection::default()))
+}
+
+/// Removes a random connector whose removal keeps the structure connected, along with its muscles.
+pub fn remove_connector<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<EdgeIndex> {
+    let components = structure.component_count();
+    let candidates: Vec<EdgeIndex> = structure.edge_indices()
+        .filter(|edge| {
+            let mut graph = structure.clone();
+            graph.remove_edge(*edge);
+            graph.component_count() == components
+        })
+        .collect();
+    let edge = pick(&candidates, rng)?;
+    structure.remove_connection(edge);
+    Some(edge)
+}
+
+/// Adds a muscle between two random connectors that don't have one yet.
+pub fn add_muscle<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<(EdgeIndex, EdgeIndex)> {
+    let edges: Vec<EdgeIndex> = structure.edge_indices().collect();
+    let mut pairs = Vec::new();
+    for (i, a) in edges.iter().enumerate() {
+        for b in edges.iter().skip(i + 1) {
+            if !structure[*a].muscle_data.contains_key(b) {
+                pairs.push((*a, *b));
+            }
+        }
+    }
+    let (a, b) = pick(&pairs, rng)?;
+    let params = MuscleParams {
+        phase: rng.gen_range(0.0..std::f32::consts::TAU),
+        ..default()
+    };
+    structure.add_muscle_data(a, b, params);
+    Some((a, b))
+}
+
+/// Removes a random muscle.
+pub fn remove_muscle<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<(EdgeIndex, EdgeIndex)> {
+    let pairs = muscle_pairs(structure);
+    let (a, b) = pick(&pairs, rng)?;
+    structure.remove_muscle_data(a, b);
+    Some((a, b))
+}
+
+/// Randomly changes the phase, frequency and strength of a random muscle.
+pub fn tweak_muscle<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<(EdgeIndex, EdgeIndex)> {
+    let pairs = muscle_pairs(structure);
+    let (a, b) = pick(&pairs, rng)?;
+    let mut params = structure.muscle_params(a, b)?.clone();
+    params.phase = (params.phase + rng.gen_range(-0.5..=0.5)).rem_euclid(std::f32::consts::TAU);
+    params.frequency = (params.frequency * rng.gen_range(0.8..=1.25)).clamp(0.05, 5.0);
+    params.max_force = (params.max_force * rng.gen_range(0.8..=1.25)).max(1.0);
+    structure.set_muscle_params(a, b, params);
+    Some((a, b))
+}
+
+/// Sets the parent of a random joint to one of its neighbours, or clears it. Neighbours that would create a parent
+/// cycle are skipped. Returns the joint changed.
+pub fn reparent<R: Rng>(structure: &mut Structure, rng: &mut R) -> Option<NodeIndex> {
+    let node = random_node(structure, rng)?;
+    let mut options: Vec<Option<NodeIndex>> = structure.neighbors(node)
+        .filter(|n| !is_ancestor(structure, node, *n))
+        .map(Some)
+        .collect();
+    options.sort();
+    options.push(None);
+    options.retain(|p| *p != structure[node].parent);
+
+    structure[node].parent = pick(&options, rng)?;
+    Some(node)
+}
+
+/// Every muscle of the structure as an ordered pair of edges.
+pub fn muscle_pairs(structure: &Structure) -> Vec<(EdgeIndex, EdgeIndex)> {
+    let mut pairs = Vec::new();
+    for edge in structure.edge_indices() {
+        for other in structure[edge].muscle_data.keys() {
+            if edge < *other {
+                pairs.push((edge, *other));
+            }
+        }
+    }
+    pairs
+}
+
+/// Whether `ancestor` is `node` or is reached by following the parents of `node`.
+fn is_ancestor(structure: &Structure, ancestor: NodeIndex, node: NodeIndex) -> bool {
+    let mut current = Some(node);
+    let mut steps = 0;
+    while let Some(n) = current {
+        if n == ancestor {
+            return true;
+        }
+        // guards against parent cycles in malformed structures
+        steps += 1;
+        if steps > structure.node_count() {
+            return false;
+        }
+        current = structure.node_weight(n).and_then(|p| p.parent);
+    }
+    false
+}
+
+/// Picks a random joint of the structure.
+fn random_node<R: Rng>(structure: &Structure, rng: &mut R) -> Option<NodeIndex> {
+    let count = structure.node_count();
+    if count == 0 {
+        return None;
+    }
+    structure.node_indices().nth(rng.gen_range(0..count))
+}
+
+fn pick<T: Copy, R: Rng>(items: &[T], rng: &mut R) -> Option<T> {
+    if items.is_empty() {
+        return None;
+    }
+    Some(items[rng.gen_range(0..items.len())])
+}
+
+/// Uniformly distributed unit vector.
+fn random_direction<R: Rng>(rng: &mut R) -> Vec3 {
+    loop {
+        let v = Vec3::new(
+            rng.gen_range(-1.0..=1.0),
+            rng.gen_range(-1.0..=1.0),
+            rng.gen_range(-1.0..=1.0),
+        );
+        let len = v.length_squared();
+        if len > 1e-4 && len <= 1.0 {
+            return v.normalize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    /// Times each operator is applied in a row.
+    const ROUNDS: usize = 200;
+
+    /// Structure of a few joints with muscles between some of their connectors.
+    fn seed(rng: &mut ChaCha8Rng) -> Structure {
+        let mut structure = Structure::with_root();
+        for _ in 0..6 {
+            add_joint(&mut structure, rng);
+        }
+        for _ in 0..3 {
+            add_muscle(&mut structure, rng);
+        }
+        structure
+    }
+
+    /// Issues breaking the invariants the operators have to keep. Joints may still end up on top of each other.
+    fn broken_invariants(structure: &Structure) -> Vec<Issue> {
+        structure.validate()
+            .into_iter()
+            .filter(|issue| !matches!(issue, Issue::ZeroLength(_) | Issue::Overlapping(..)))
+            .collect()
+    }
+
+    #[test]
+    fn operators_keep_invariants() {
+        for mutation in Mutation::ALL {
+            let mut rng = ChaCha8Rng::seed_from_u64(1);
+            let mut structure = seed(&mut rng);
+            for round in 0..ROUNDS {
+                mutation.apply(&mut structure, &mut rng);
+                // keeps removing operators from running out of joints, connectors and muscles
+                if round % 4 == 0 {
+                    add_joint(&mut structure, &mut rng);
+                    add_muscle(&mut structure, &mut rng);
+                }
+                assert_eq!(broken_invariants(&structure), vec![], "{:?} in round {}", mutation, round);
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_mutations_keep_invariants() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let mut structure = seed(&mut rng);
+        for round in 0..ROUNDS {
+            let mutation = mutate(&mut structure, &mut rng);
+            assert_eq!(broken_invariants(&structure), vec![], "{:?} in round {}", mutation, round);
+        }
+    }
+}
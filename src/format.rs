@@ -0,0 +1,394 @@
+//! Structure file format.
+//!
+//! Structure files hold a versioned envelope around the joints, connectors and muscles of the structure, which refer
+//! to each other by their ids:
+//!
+//! ```text
+//! (
+//!     version: 2,
+//!     metadata: (name: "crawler", author: "", created: Some(1718000000), modified: Some(1718000000)),
+//!     structure: (
+//!         joints: [(id: 0, pos: (0.0, 0.0, 0.0), parent: None, ...), (id: 1, pos: (0.0, 4.0, 0.0), parent: Some(0), ...)],
+//!         connectors: [(id: 0, joints: (1, 0), joint: Ball, ...)],
+//!         muscles: [(connectors: (0, 1), params: (...))],
+//!     ),
+//! )
+//! ```
+//!
+//! The elements are written without the holes left in the graph by removed ones, and get new indices when loaded.
+//! Files written before the envelope existed hold the bare petgraph graph and are read as version 0, version 1 wraps
+//! the same graph in the envelope. Older versions are upgraded on load by the migrations, one version at a time. New
+//! fields that can be given a serde default don't need a new version, any other change to the format bumps
+//! `FORMAT_VERSION` and adds a migration from the previous version.
+
+use std::{collections::HashMap, fmt, time::{SystemTime, UNIX_EPOCH}};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::structure::{
+    default_friction, default_mass, default_radius, Connection, ConnectionLimits, JointType, MuscleParams, Point,
+    Structure,
+};
+
+/// Version of the structure files written by this build.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Upgrades a file of the version at its index to the next version.
+const MIGRATIONS: [fn(&mut StructureFile); FORMAT_VERSION as usize] = [
+    migrate_v0,
+    migrate_v1,
+];
+
+/// Information about the creature kept in its file. Times are in seconds since the unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Resource)]
+#[serde(default)]
+pub struct Metadata {
+    pub name: String,
+    pub author: String,
+    pub created: Option<u64>,
+    pub modified: Option<u64>,
+}
+
+impl Metadata {
+    /// Sets the modification time to now, and the creation time too if it isn't known.
+    pub fn touch(&mut self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .ok();
+        self.modified = now;
+        if self.created.is_none() {
+            self.created = now;
+        }
+    }
+}
+
+/// Contents of a structure file.
+#[derive(Debug, Clone, Default)]
+pub struct StructureFile {
+    pub version: u32,
+    pub metadata: Metadata,
+    pub structure: Structure,
+}
+
+/// Only the version of a structure file, read first to know how to read the rest. Bare graphs have none.
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default)]
+    version: u32,
+}
+
+/// Envelope of version 1, holding the petgraph graph.
+#[derive(Deserialize)]
+struct GraphFile {
+    #[serde(default)]
+    metadata: Metadata,
+    structure: Structure,
+}
+
+/// Envelope of the current version. `M` is the metadata, borrowed when writing.
+#[derive(Serialize, Deserialize)]
+struct StoredFile<M> {
+    version: u32,
+    #[serde(default)]
+    metadata: M,
+    structure: StoredStructure,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredStructure {
+    joints: Vec<StoredJoint>,
+    #[serde(default)]
+    connectors: Vec<StoredConnector>,
+    #[serde(default)]
+    muscles: Vec<StoredMuscle>,
+}
+
+/// `Point` referring to its parent by id.
+#[derive(Serialize, Deserialize)]
+struct StoredJoint {
+    id: u32,
+    pos: Vec3,
+    #[serde(default)]
+    parent: Option<u32>,
+    #[serde(default = "default_mass")]
+    mass: f32,
+    #[serde(default = "default_radius")]
+    radius: f32,
+    #[serde(default = "default_friction")]
+    friction: f32,
+}
+
+/// `Connection` referring to its joints by id.
+#[derive(Serialize, Deserialize)]
+struct StoredConnector {
+    id: u32,
+    joints: (u32, u32),
+    #[serde(default)]
+    joint: JointType,
+    #[serde(default)]
+    limits: ConnectionLimits,
+}
+
+/// Muscle referring to its anchors by id. Written once, unlike the `muscle_data` kept on both anchors.
+#[derive(Serialize, Deserialize)]
+struct StoredMuscle {
+    connectors: (u32, u32),
+    #[serde(default)]
+    params: MuscleParams,
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+    /// Error: File is not valid RON or doesn't match the format of its version.
+    Parse(ron::error::SpannedError),
+    /// Error: File was written by a newer version.
+    Version(u32),
+    /// Error: Two joints or two connectors have the same id.
+    DuplicateId(&'static str, u32),
+    /// Error: A joint or connector is referred to by an id no joint or connector has.
+    UnknownId(&'static str, u32),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Parse(e) =>
+                write!(f, "{}:{}: {}", e.position.line, e.position.col, e.code),
+            FormatError::Version(version) =>
+                write!(f, "format version {} is newer than the supported version {}", version, FORMAT_VERSION),
+            FormatError::DuplicateId(kind, id) =>
+                write!(f, "more than one {} has id {}", kind, id),
+            FormatError::UnknownId(kind, id) =>
+                write!(f, "no {} has id {}", kind, id),
+        }
+    }
+}
+
+impl StructureFile {
+    /// Reads a structure file of any version up to `FORMAT_VERSION`, upgrading it to the current version.
+    pub fn from_ron(data: &[u8]) -> Result<Self, FormatError> {
+        let header: Header = ron::de::from_bytes(data).map_err(FormatError::Parse)?;
+        let (metadata, structure) = match header.version {
+            0 => (Metadata::default(), ron::de::from_bytes(data).map_err(FormatError::Parse)?),
+            1 => {
+                let file: GraphFile = ron::de::from_bytes(data).map_err(FormatError::Parse)?;
+                (file.metadata, file.structure)
+            },
+            FORMAT_VERSION => {
+                let file: StoredFile<Metadata> = ron::de::from_bytes(data).map_err(FormatError::Parse)?;
+                (file.metadata, file.structure.into_structure()?)
+            },
+            version => return Err(FormatError::Version(version)),
+        };
+
+        let mut file = StructureFile {
+            version: header.version,
+            metadata,
+            structure,
+        };
+        for migrate in MIGRATIONS[file.version as usize..].iter() {
+            migrate(&mut file);
+            file.version += 1;
+        }
+        Ok(file)
+    }
+}
+
+impl StoredStructure {
+    /// Lists the elements of the structure, skipping the holes of the graph. Parents that don't exist are dropped.
+    fn from_structure(structure: &Structure) -> Self {
+        let joints = structure.node_indices()
+            .map(|node| {
+                let point = &structure[node];
+                StoredJoint {
+                    id: point.id,
+                    pos: point.pos,
+                    parent: point.parent.and_then(|p| structure.node_weight(p)).map(|p| p.id),
+                    mass: point.mass,
+                    radius: point.radius,
+                    friction: point.friction,
+                }
+            })
+            .collect();
+
+        let mut connectors = Vec::new();
+        let mut muscles = Vec::new();
+        for edge in structure.edge_indices() {
+            let (a, b) = structure.edge_endpoints(edge).unwrap();
+            let connection = &structure[edge];
+            connectors.push(StoredConnector {
+                id: connection.id,
+                joints: (structure[a].id, structure[b].id),
+                joint: connection.joint.clone(),
+                limits: connection.limits.clone(),
+            });
+
+            // each muscle is written from its lower anchor, or from the only one holding it
+            for (other, params) in connection.muscle_data.iter() {
+                let Some(other_connection) = structure.edge_weight(*other) else {
+                    continue;
+                };
+                if edge.index() < other.index() || !other_connection.muscle_data.contains_key(&edge) {
+                    muscles.push(StoredMuscle {
+                        connectors: (connection.id, other_connection.id),
+                        params: params.clone(),
+                    });
+                }
+            }
+        }
+
+        StoredStructure { joints, connectors, muscles }
+    }
+
+    /// Builds the graph, giving the elements new indices and resolving the ids they refer to each other by.
+    fn into_structure(self) -> Result<Structure, FormatError> {
+        let mut structure = Structure::default();
+
+        let mut nodes = HashMap::new();
+        for joint in self.joints.iter() {
+            let node = structure.add_node(Point {
+                entityid: None,
+                id: joint.id,
+                pos: joint.pos,
+                parent: None,
+                mass: joint.mass,
+                radius: joint.radius,
+                friction: joint.friction,
+            });
+            if nodes.insert(joint.id, node).is_some() {
+                return Err(FormatError::DuplicateId("joint", joint.id));
+            }
+        }
+        let node = |id: u32| nodes.get(&id).copied().ok_or(FormatError::UnknownId("joint", id));
+        for joint in self.joints.iter() {
+            if let Some(parent) = joint.parent {
+                structure[node(joint.id)?].parent = Some(node(parent)?);
+            }
+        }
+
+        let mut edges = HashMap::new();
+        for connector in self.connectors {
+            let edge = structure.add_edge(node(connector.joints.0)?, node(connector.joints.1)?, Connection {
+                id: connector.id,
+                joint: connector.joint,
+                limits: connector.limits,
+                ..default()
+            });
+            if edges.insert(connector.id, edge).is_some() {
+                return Err(FormatError::DuplicateId("connector", connector.id));
+            }
+        }
+        let edge = |id: u32| edges.get(&id).copied().ok_or(FormatError::UnknownId("connector", id));
+        for muscle in self.muscles {
+            structure.add_muscle_data(edge(muscle.connectors.0)?, edge(muscle.connectors.1)?, muscle.params);
+        }
+
+        Ok(structure)
+    }
+}
+
+/// Serializes the structure and its metadata in the current version of the format.
+pub fn to_ron(structure: &Structure, metadata: &Metadata) -> Result<String, ron::Error> {
+    let file = StoredFile {
+        version: FORMAT_VERSION,
+        metadata,
+        structure: StoredStructure::from_structure(structure),
+    };
+    ron::ser::to_string_pretty(
+        &file,
+        ron::ser::PrettyConfig::new()
+            .depth_limit(3)
+            .separate_tuple_members(true)
+            .enumerate_arrays(true)
+    )
+}
+
+/// Version 0 is the bare graph, which is kept as is in the envelope. Nothing is known about the creature.
+fn migrate_v0(_file: &mut StructureFile) {}
+
+/// Version 1 has no ids, the elements are numbered by their indices.
+fn migrate_v1(file: &mut StructureFile) {
+    file.structure.assign_ids();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Joints as (id, position, parent id), connectors as (id, joint ids) and muscles as the ids of their anchors,
+    /// each sorted by id, which stay the same however the elements are indexed.
+    type ById = (Vec<(u32, Vec3, Option<u32>)>, Vec<(u32, (u32, u32))>, Vec<(u32, u32)>);
+
+    fn by_id(structure: &Structure) -> ById {
+        let mut joints: Vec<_> = structure.node_weights()
+            .map(|p| (p.id, p.pos, p.parent.map(|parent| structure[parent].id)))
+            .collect();
+        joints.sort_by_key(|joint| joint.0);
+
+        let mut connectors = Vec::new();
+        let mut muscles = Vec::new();
+        for edge in structure.edge_indices() {
+            let (a, b) = structure.edge_endpoints(edge).unwrap();
+            let (a, b) = (structure[a].id, structure[b].id);
+            connectors.push((structure[edge].id, (a.min(b), a.max(b))));
+            muscles.extend(structure[edge].muscle_data.keys().map(|other| (structure[edge].id, structure[*other].id)));
+        }
+        connectors.sort();
+        muscles.sort();
+        (joints, connectors, muscles)
+    }
+
+    fn round_trip(structure: &Structure) -> Structure {
+        let data = to_ron(structure, &Metadata::default()).unwrap();
+        StructureFile::from_ron(data.as_bytes()).unwrap().structure
+    }
+
+    #[test]
+    fn bare_graph_round_trip() {
+        let data = "(
+            nodes: [(pos: (0.0, 0.0, 0.0), parent: None), (pos: (0.0, 4.0, 0.0), parent: Some(0)),
+                (pos: (3.0, 0.0, 0.0), parent: Some(0))],
+            node_holes: [],
+            edge_property: undirected,
+            edges: [Some((0, 1, (muscle_data: [1]))), Some((0, 2, (muscle_data: [0])))],
+        )";
+        let file = StructureFile::from_ron(data.as_bytes()).unwrap();
+        assert_eq!(file.version, FORMAT_VERSION);
+        assert_eq!(file.structure.validate(), Vec::new());
+
+        let expected = (
+            vec![(0, Vec3::ZERO, None), (1, Vec3::new(0.0, 4.0, 0.0), Some(0)), (2, Vec3::new(3.0, 0.0, 0.0), Some(0))],
+            vec![(0, (0, 1)), (1, (0, 2))],
+            vec![(0, 1), (1, 0)],
+        );
+        assert_eq!(by_id(&file.structure), expected);
+        assert_eq!(by_id(&round_trip(&file.structure)), expected);
+    }
+
+    #[test]
+    fn round_trip_after_removing_joint() {
+        let mut structure = Structure::default();
+        let root = structure.add_point(Point::default());
+        let removed = structure.add_point(Point { pos: Vec3::X, parent: Some(root), ..default() });
+        let n2 = structure.add_point(Point { pos: Vec3::Y, parent: Some(root), ..default() });
+        let n3 = structure.add_point(Point { pos: Vec3::new(0.0, 1.0, 1.0), parent: Some(n2), ..default() });
+        structure.add_connection(root, removed, Connection::default());
+        let e1 = structure.add_connection(root, n2, Connection::default());
+        let e2 = structure.add_connection(n2, n3, Connection::default());
+        structure.add_muscle_data(e1, e2, MuscleParams { phase: 1.0, ..default() });
+        structure.remove_node(removed);
+
+        let loaded = round_trip(&structure);
+        assert_eq!(loaded.validate(), Vec::new());
+        assert_eq!(by_id(&loaded), by_id(&structure));
+
+        // the hole left by the removed joint is closed, the ids still lead to the same elements
+        let n3 = loaded.node_by_id(3).unwrap();
+        assert_eq!(loaded[n3].parent, loaded.node_by_id(2));
+        let (e1, e2) = (loaded.edge_by_id(1).unwrap(), loaded.edge_by_id(2).unwrap());
+        assert_eq!(loaded[e1].muscle_data.get(&e2).map(|params| params.phase), Some(1.0));
+        assert_eq!(loaded[e2].muscle_data.get(&e1).map(|params| params.phase), Some(1.0));
+    }
+}
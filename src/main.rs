@@ -2,9 +2,13 @@ use bevy::prelude::*;
 use selection::SelectionPlugin;
 
 mod camera;
+mod cli;
 mod editor;
+mod evolution;
+mod format;
 mod selection;
-// mod observer;
+mod observer;
+mod physics;
 mod structure;
 mod util;
 
@@ -23,21 +27,53 @@ pub struct Editor;
 pub struct Observer;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    match command {
+        cli::Command::Editor(file) => run_app(file),
+        cli::Command::Evolve(args) => {
+            if let Err(e) = cli::evolve(args) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        cli::Command::Lint(file) => {
+            if let Err(e) = cli::lint(file) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn run_app(file: String) {
+    let current_file = editor::file::CurrentFile(file.into());
     App::new()
         .add_plugins((
             DefaultPlugins.set(WindowPlugin {
                 primary_window: Some(Window {
-                    title: "test".to_string(),
+                    title: format!("evol - {}", current_file.name()),
                     resolution: (700., 700.,).into(),
                     ..default()
                 }),
+                // closing asks to save unsaved changes first, see editor::autosave
+                close_when_requested: false,
                 ..default()
             }),
             MeshPickingPlugin,
             SelectionPlugin,
             editor::EditorPlugin,
+            observer::ObserverPlugin,
         ))
         .init_state::<GameState>()
+        .insert_resource(current_file)
         .init_resource::<util::JointMeshes>()
         .init_resource::<util::JointMaterial>()
         .run();
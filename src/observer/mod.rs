@@ -1,255 +1,185 @@
 use bevy::prelude::*;
-use bevy_mod_picking::prelude::RaycastPickCamera;
-// use bevy_mod_picking::PickingCameraBundle;
-use bevy_rapier3d::prelude::*;
-use petgraph::stable_graph::EdgeIndex;
 
-use crate::{pgraph::PGraph, util::*, GameState};
+use crate::{physics::{SimConfig, Simulation}, structure::Structure, util::*, GameState};
 
 mod joint;
-mod muscle;
 
 pub struct ObserverPlugin;
 impl Plugin for ObserverPlugin {
     fn build(&self, app: &mut App) {
-        app
-        .add_plugins((
-            RapierPhysicsPlugin::<NoUserData>::default(),
-            RapierDebugRenderPlugin::default(),
-        ))
+        app.init_resource::<SimState>()
+        .insert_resource(Time::<Fixed>::from_seconds(SimConfig::default().dt as f64))
         .add_systems(
             OnEnter(GameState::Observer),
             (
                 setup_graphics,
-                setup_physics,
-                deserialize_pgraph,
-                setup_joint_physics.after(deserialize_pgraph)
+                setup_ground,
+                spawn_structure,
+                setup_ui,
             )
         )
+        .add_systems(
+            FixedUpdate,
+            joint::step_simulation.run_if(in_state(GameState::Observer))
+        )
         .add_systems(
             Update,
             (
-                muscle::clear_forces,
-                (
-                    joint::update_connector,
-                    muscle::update_muscles,
-                    muscle::activate_muscle,
-                    muscle::contract_muscle,
-                    pause_sim,
-                ).after(muscle::clear_forces),
+                crate::camera::pan_orbit_camera,
+                crate::camera::focus_selected_entity,
+
+                joint::sync_joints,
+                pause_sim,
+                editbut_interact,
             ).run_if(in_state(GameState::Observer))
         )
-
-        .add_systems(OnEnter(GameState::Observer), test)
-        .add_systems(Update, testbut_interact.run_if(in_state(GameState::Observer)))
-
-        .add_systems(OnExit(GameState::Observer), crate::util::despawn_all::<crate::Observer>);
+        .add_systems(
+            PostUpdate,
+            (
+                crate::editor::joint::update_connector,
+                crate::editor::muscle::update_muscles,
+            ).run_if(in_state(GameState::Observer))
+        )
+        .add_systems(
+            OnExit(GameState::Observer),
+            (
+                despawn_all::<crate::Observer>,
+                crate::selection::clear_selection,
+            )
+        );
     }
 }
 
-fn deserialize_pgraph(
+/// Observer simulation state.
+#[derive(Resource, Default)]
+pub struct SimState {
+    pub paused: bool,
+}
+
+/// Simulation of the structure being observed.
+#[derive(Resource)]
+pub struct ObserverSim(pub Simulation);
+
+/// Spawns the in-memory structure and builds its simulation.
+fn spawn_structure(
     mut commands: Commands,
-    mut graph: ResMut<PGraph>,
+    mut structure: ResMut<Structure>,
     meshes: Res<JointMeshes>,
     materials: Res<JointMaterial>,
 ) {
-    let graph_data = &std::fs::read("./pgraph.ron").unwrap();
-    graph.0 = ron::de::from_bytes(graph_data).unwrap();
-    println!("** GENERATED GRAPH");
-
-    let joint_c = (
-        RigidBody::Dynamic,
-        Collider::ball(1.0),
-        Friction::coefficient(3.0),
-        ExternalForce::default(),
-        GravityScale(2.0)
-    );
-
-    let muscle_c = (
-        muscle::MuscleState::default(),
-        muscle::MuscleLength::default(), 
-    );
-    let conn_c = (
-        // Collider::ball(0.2),
-        // RigidBody::Dynamic,
-        // Friction::coefficient(3.0),
-        // ExternalForce::default(),
-        // GravityScale(2.0)
-    );
-
-    graph.create(
-        &mut commands, 
-        meshes, 
-        materials, 
-        joint_c, 
-        conn_c,
-        muscle_c,
+    structure.sync_muscle_data();
+
+    structure.create(
+        &mut commands,
+        meshes,
+        materials,
+        (),
+        (),
+        (),
         crate::Observer
     );
-}
 
-fn setup_joint_physics(
-    pgraph: ResMut<PGraph>,
-    mut commands: Commands,
-) {
-    // for edge in pgraph.0.edge_indices() {
-    //     let (n1, n2) = pgraph.0.edge_endpoints(edge).unwrap();
-    //     let n1_weight = pgraph.0.node_weight(n1).unwrap();
-    //     let n2_weight = pgraph.0.node_weight(n2).unwrap();
-
-    //     let s_joint: SphericalJointBuilder = SphericalJointBuilder::new().local_anchor2(n2_weight.pos-n1_weight.pos);
-
-    //     commands.entity(n1_weight.entityid.unwrap()).insert(ImpulseJoint::new(n2_weight.entityid.unwrap(), s_joint));
-    // }
-    let p_edge: Option<EdgeIndex> = None;
-    for edge in pgraph.0.edge_indices() {
-        let edge_weight = pgraph.0.edge_weight(edge).unwrap();
-        let (n1, n2) = pgraph.0.edge_endpoints(edge).unwrap();
-        let n1_weight = pgraph.0.node_weight(n1).unwrap();
-        let n2_weight = pgraph.0.node_weight(n2).unwrap();
-
-        let s_joint: SphericalJointBuilder = SphericalJointBuilder::new()
-            .local_anchor1((n2_weight.pos-n1_weight.pos)/2.0)
-            .local_anchor2((n1_weight.pos-n2_weight.pos)/2.0);
-
-        // println!("1: {:?} \n2: {:?}", (n2_weight.pos-n1_weight.pos)/2.0, (n1_weight.pos-n2_weight.pos)/2.0);
-
-        if let Some(prev_edge) = p_edge {
-            let pe_weight = pgraph.0.edge_weight(prev_edge).unwrap();
-            commands.entity(edge_weight.entityid.unwrap()).insert(ImpulseJoint::new(pe_weight.entityid.unwrap(), s_joint));
-        }
-    }
+    commands.insert_resource(ObserverSim(Simulation::new(&structure, SimConfig::default())));
 }
 
 fn setup_graphics(mut commands: Commands) {
-    // Add a camera so we can see the debug-render.
     commands.spawn((
-        Camera3dBundle {
-            transform: Transform::from_xyz(0.0, 10.0, 100.0).looking_at(Vec3::ZERO, Vec3::Y),
-            ..Default::default()
-        },
-        RaycastPickCamera::default(),
-        crate::camera::PanOrbitCamera {
-            radius: 40.,
-            focus: Vec3::ZERO,
-            ..Default::default()
+        crate::camera::PanOrbitCamera,
+        crate::camera::PanOrbitState {
+            radius: 40.0,
+            ..default()
         },
         crate::Observer
     ));
 
     commands.spawn((
-        PointLightBundle {
-            point_light: PointLight {
-                intensity: 1500.0,
-                shadows_enabled: true,
-                ..default()
-            },
-            transform: Transform::from_xyz(4.0, 8.0, 4.0),
+        PointLight {
+            intensity: 1_500_000.0,
+            shadows_enabled: true,
             ..default()
         },
+        Transform::from_xyz(4.0, 8.0, 4.0),
         crate::Observer
     ));
 }
 
-fn setup_physics( 
+fn setup_ground(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    /* Create the ground. */
     commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Plane { size: 1000.0, ..default() })),
-            material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
-            transform: Transform::from_xyz(0., -5., 0.),
-            ..default()
-        },
-        Collider::cuboid(100.0, 0.1, 100.0),
-        Restitution::coefficient(0.0),
-        Friction::coefficient(3.0),
+        Mesh3d(meshes.add(Plane3d::default().mesh().size(1000.0, 1000.0))),
+        MeshMaterial3d(materials.add(Color::srgb(0.3, 0.5, 0.3))),
+        Transform::from_xyz(0.0, SimConfig::default().ground_height, 0.0),
         crate::Observer,
     ));
-
 }
 
 #[derive(Component)]
-struct TestBut;
+struct EditBut;
 
-fn test(
+fn setup_ui(
     mut commands: Commands,
-    asset_server: Res<AssetServer>, 
+    asset_server: Res<AssetServer>,
 ) {
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                // size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-                position_type: PositionType::Absolute,
+    commands.spawn((
+        Node {
+            width: Val::Percent(100.0),
+            position_type: PositionType::Absolute,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        crate::Observer
+    )).with_children(|parent| {
+        parent.spawn((
+            Button,
+            Node {
+                width: Val::Px(80.0),
+                height: Val::Px(30.0),
+                margin: UiRect {
+                    top: Val::Px(5.),
+                    ..default()
+                },
                 justify_content: JustifyContent::Center,
-                align_items: AlignItems::FlexEnd,
+                align_items: AlignItems::Center,
                 ..default()
             },
-            ..default()
-        }).with_children(|parent| {
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        // size: Size::new(Val::Px(80.0), Val::Px(30.0)),
-                        margin: UiRect {
-                            top: Val::Px(5.),
-                            ..default()
-                        },
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..default()
-                    },
-                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
-                    ..default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(TextBundle {
-                        text: Text::from_section(
-                            "GO",
-                            TextStyle {
-                                font: asset_server.load("fonts/FiraCode-Regular.ttf"),
-                                font_size: 15.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                        ),
-                        ..default()
-                    });
-                })
-                .insert(TestBut);
-        }).insert(crate::Observer);
+            BackgroundColor(Color::srgb(0.15, 0.15, 0.15)),
+            EditBut,
+        )).with_child((
+            Text::new("EDIT"),
+            TextFont {
+                font: asset_server.load("fonts/FiraCode-Regular.ttf"),
+                font_size: 15.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+        ));
+    });
 }
 
-
-fn testbut_interact(
-    // mut commands: Commands,
+fn editbut_interact(
     mut state: ResMut<NextState<GameState>>,
-    mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<Button>, With<TestBut>),
+    interaction_query: Query<
+        &Interaction,
+        (Changed<Interaction>, With<Button>, With<EditBut>),
     >,
 ) {
-    for (interaction, _) in interaction_query.iter_mut() {
-        match *interaction {
-            Interaction::Pressed => {
-                println!("Switching State to GameState::Editor");
-                state.set(GameState::Editor);
-            }
-            Interaction::Hovered => {
-            }
-            Interaction::None => {
-            }
+    for interaction in interaction_query.iter() {
+        if *interaction == Interaction::Pressed {
+            println!("Switching State to GameState::Editor");
+            state.set(GameState::Editor);
         }
     }
 }
 
 fn pause_sim(
-    input: Res<Input<KeyCode>>,
-    mut config: ResMut<RapierConfiguration>,
+    input: Res<ButtonInput<KeyCode>>,
+    mut sim_state: ResMut<SimState>,
 ) {
-    if input.just_pressed(KeyCode::Q) {
-        config.physics_pipeline_active = !config.physics_pipeline_active;
+    if input.just_pressed(KeyCode::KeyQ) {
+        sim_state.paused = !sim_state.paused;
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,573 @@
+//! Deterministic mass-spring simulation of a [`Structure`].
+//!
+//! Joints are point masses integrated with Verlet integration at a fixed timestep, connectors are distance
+//! constraints and muscles are actuated springs pulling on the midpoints of their two anchor connectors. The joint
+//! types of connectors become angle and hinge constraints between connectors sharing a joint. The simulation does not
+//! depend on any ECS state, so it can be run headlessly. Given the same structure and config it always produces the
+//! same result.
+
+use bevy::prelude::*;
+use petgraph::{stable_graph::{EdgeIndex, NodeIndex}, visit::EdgeRef};
+
+use crate::structure::{JointType, MuscleParams, Structure};
+
+/// Global parameters of a simulation.
+#[derive(Debug, Clone, Copy)]
+pub struct SimConfig {
+    /// Length of a single step in seconds.
+    pub dt: f32,
+    /// Number of constraint solver passes per step.
+    pub iterations: usize,
+    pub gravity: Vec3,
+    /// Height of the ground plane.
+    pub ground_height: f32,
+    /// Fraction of velocity lost every step.
+    pub damping: f32,
+    /// Fraction of the rest length a muscle contracts and extends by.
+    pub muscle_amplitude: f32,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            dt: 1.0 / 60.0,
+            iterations: 8,
+            gravity: Vec3::new(0.0, -19.6, 0.0),
+            ground_height: -5.0,
+            damping: 0.01,
+            muscle_amplitude: 0.3,
+        }
+    }
+}
+
+/// Point mass of a joint.
+#[derive(Debug, Clone)]
+pub struct Body {
+    pub node: NodeIndex,
+    pub pos: Vec3,
+    pub prev_pos: Vec3,
+    pub inv_mass: f32,
+    pub radius: f32,
+    pub friction: f32,
+    force: Vec3,
+}
+
+/// Distance constraint of a connector between two bodies. The length of the rod is kept between `min` and `max`, and
+/// spring connectors are additionally pulled towards `rest` by `spring`.
+#[derive(Debug, Clone)]
+pub struct Rod {
+    pub a: usize,
+    pub b: usize,
+    pub rest: f32,
+    pub min: f32,
+    pub max: f32,
+    pub spring: Option<RodSpring>,
+}
+
+/// Spring of a telescopic connector.
+#[derive(Debug, Clone)]
+pub struct RodSpring {
+    pub stiffness: f32,
+    pub damping: f32,
+    prev_len: f32,
+}
+
+/// Keeps the angle between the bodies `a` and `b` around the body `joint` between `min` and `max` radians.
+#[derive(Debug, Clone)]
+pub struct AngleLimit {
+    pub joint: usize,
+    pub a: usize,
+    pub b: usize,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Keeps the connector from `joint` to `end` rotating only around a hinge axis. The axis is carried along by the rod
+/// from `joint` to `other`, so it turns with the rest of the structure.
+#[derive(Debug, Clone)]
+pub struct Hinge {
+    pub joint: usize,
+    pub end: usize,
+    pub other: usize,
+    /// Hinge axis when the simulation was built.
+    pub axis: Vec3,
+    /// Direction from `joint` to `other` when the simulation was built.
+    pub other_dir: Vec3,
+    /// Component of the connector direction along the axis, kept at its starting value.
+    pub offset: f32,
+}
+
+/// Actuated spring between the midpoints of two rods.
+#[derive(Debug, Clone)]
+pub struct Spring {
+    pub a: usize,
+    pub b: usize,
+    pub rest: f32,
+    pub stiffness: f32,
+    pub max_force: f32,
+    pub damping: f32,
+    pub frequency: f32,
+    pub phase: f32,
+    prev_len: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub config: SimConfig,
+    pub bodies: Vec<Body>,
+    pub rods: Vec<Rod>,
+    pub springs: Vec<Spring>,
+    pub angle_limits: Vec<AngleLimit>,
+    pub hinges: Vec<Hinge>,
+    time: f32,
+}
+
+impl Simulation {
+    /// Builds a simulation from the current positions of the structure.
+    pub fn new(structure: &Structure, config: SimConfig) -> Self {
+        let mut bodies = Vec::new();
+        let mut node_map = bevy::utils::HashMap::new();
+        for node in structure.node_indices() {
+            let point = &structure[node];
+            node_map.insert(node, bodies.len());
+            bodies.push(Body {
+                node,
+                pos: point.pos,
+                prev_pos: point.pos,
+                // massless joints are treated as fixed in place
+                inv_mass: if point.mass > 0.0 { 1.0 / point.mass } else { 0.0 },
+                radius: point.radius,
+                friction: point.friction,
+                force: Vec3::ZERO,
+            });
+        }
+
+        let mut rods = Vec::new();
+        let mut angle_limits = Vec::new();
+        let mut hinges = Vec::new();
+        let mut edge_map = bevy::utils::HashMap::new();
+        for edge in structure.edge_indices() {
+            let (n1, n2) = structure.edge_endpoints(edge).unwrap();
+            let (a, b) = (node_map[&n1], node_map[&n2]);
+            let connection = &structure[edge];
+            let rest = bodies[a].pos.distance(bodies[b].pos);
+            edge_map.insert(edge, rods.len());
+
+            let (min, max, spring) = match connection.joint {
+                JointType::Spring { stiffness, damping } => (
+                    connection.limits.min_length.unwrap_or(0.0),
+                    connection.limits.max_length.unwrap_or(f32::INFINITY),
+                    Some(RodSpring { stiffness, damping, prev_len: rest }),
+                ),
+                _ => (rest, rest, None),
+            };
+            rods.push(Rod { a, b, rest, min, max, spring });
+
+            // constraints against every other connector sharing either joint
+            for (joint, end) in [(n1, n2), (n2, n1)] {
+                let mut others: Vec<NodeIndex> = structure.edges(joint)
+                    .filter(|e| e.id() != edge)
+                    .map(|e| if e.source() == joint { e.target() } else { e.source() })
+                    .collect();
+                others.sort();
+
+                let (j, e) = (node_map[&joint], node_map[&end]);
+                for other in others {
+                    let o = node_map[&other];
+                    let dir = bodies[e].pos - bodies[j].pos;
+                    let other_dir = bodies[o].pos - bodies[j].pos;
+
+                    if connection.joint == JointType::Rigid {
+                        let angle = dir.angle_between(other_dir);
+                        angle_limits.push(AngleLimit { joint: j, a: e, b: o, min: angle, max: angle });
+                    } else if connection.limits.min_angle.is_some() || connection.limits.max_angle.is_some() {
+                        angle_limits.push(AngleLimit {
+                            joint: j,
+                            a: e,
+                            b: o,
+                            min: connection.limits.min_angle.unwrap_or(0.0),
+                            max: connection.limits.max_angle.unwrap_or(std::f32::consts::PI),
+                        });
+                    }
+
+                    if let JointType::Hinge { axis } = connection.joint {
+                        let axis = axis.normalize_or_zero();
+                        if axis == Vec3::ZERO {
+                            continue;
+                        }
+                        hinges.push(Hinge {
+                            joint: j,
+                            end: e,
+                            other: o,
+                            axis,
+                            other_dir: other_dir.normalize_or_zero(),
+                            offset: dir.normalize_or_zero().dot(axis),
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut sim = Simulation {
+            config,
+            bodies,
+            rods,
+            springs: Vec::new(),
+            angle_limits,
+            hinges,
+            time: 0.0,
+        };
+
+        // muscles are stored on both of their edges, only keep one of each pair
+        let mut pairs: Vec<(EdgeIndex, EdgeIndex, &MuscleParams)> = structure
+            .edge_indices()
+            .flat_map(|edge| {
+                structure[edge].muscle_data.iter()
+                    .filter(move |(other, _)| edge < **other)
+                    .map(move |(other, params)| (edge, *other, params))
+            })
+            .collect();
+        pairs.sort_by_key(|(e1, e2, _)| (*e1, *e2));
+
+        for (e1, e2, params) in pairs {
+            let (Some(&a), Some(&b)) = (edge_map.get(&e1), edge_map.get(&e2)) else {
+                continue;
+            };
+            let len = sim.rod_midpoint(a).distance(sim.rod_midpoint(b));
+            sim.springs.push(Spring {
+                a,
+                b,
+                rest: params.rest_length.unwrap_or(len),
+                stiffness: params.stiffness,
+                max_force: params.max_force,
+                damping: params.damping,
+                frequency: params.frequency,
+                phase: params.phase,
+                prev_len: len,
+            });
+        }
+
+        sim
+    }
+
+    /// Advances the simulation by a single fixed step.
+    pub fn step(&mut self) {
+        let dt = self.config.dt;
+
+        self.apply_muscles();
+        self.apply_rod_springs();
+
+        for body in self.bodies.iter_mut().filter(|body| body.inv_mass > 0.0) {
+            let accel = self.config.gravity + body.force * body.inv_mass;
+            let velocity = (body.pos - body.prev_pos) * (1.0 - self.config.damping);
+            body.prev_pos = body.pos;
+            body.pos += velocity + accel * dt * dt;
+            body.force = Vec3::ZERO;
+        }
+
+        // how far the ground pushed each body up, which the friction of the step is proportional to
+        let mut normal = vec![0.0; self.bodies.len()];
+        for _ in 0..self.config.iterations {
+            self.solve_rods();
+            self.solve_angle_limits();
+            self.solve_hinges();
+            self.solve_ground(&mut normal);
+        }
+        self.apply_friction(&normal);
+
+        self.time += dt;
+    }
+
+    /// Runs the simulation for the given amount of seconds, rounded down to whole steps.
+    pub fn run(&mut self, seconds: f32) {
+        let steps = (seconds / self.config.dt) as usize;
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+
+    /// Mass weighted average position of all joints.
+    pub fn center_of_mass(&self) -> Vec3 {
+        let mut total = 0.0;
+        let mut center = Vec3::ZERO;
+        for body in self.bodies.iter().filter(|body| body.inv_mass > 0.0) {
+            let mass = 1.0 / body.inv_mass;
+            center += body.pos * mass;
+            total += mass;
+        }
+        if total > 0.0 {
+            center / total
+        } else {
+            Vec3::ZERO
+        }
+    }
+
+    fn rod_midpoint(&self, rod: usize) -> Vec3 {
+        let rod = &self.rods[rod];
+        (self.bodies[rod.a].pos + self.bodies[rod.b].pos) / 2.0
+    }
+
+    fn apply_muscles(&mut self) {
+        for i in 0..self.springs.len() {
+            let spring = &self.springs[i];
+            let p1 = self.rod_midpoint(spring.a);
+            let p2 = self.rod_midpoint(spring.b);
+
+            let delta = p2 - p1;
+            let len = delta.length();
+            let speed = (len - spring.prev_len) / self.config.dt;
+            self.springs[i].prev_len = len;
+            let spring = &self.springs[i];
+            if len <= f32::EPSILON {
+                continue;
+            }
+            let dir = delta / len;
+
+            let activation = (std::f32::consts::TAU * spring.frequency * self.time + spring.phase).sin();
+            let target = spring.rest * (1.0 + self.config.muscle_amplitude * activation);
+            let force = (spring.stiffness * (len - target) + spring.damping * speed)
+                .clamp(-spring.max_force, spring.max_force);
+
+            // force is split evenly between the two joints of each connector
+            let (a, b) = (spring.a, spring.b);
+            for (rod, sign) in [(a, 1.0), (b, -1.0)] {
+                let (j1, j2) = (self.rods[rod].a, self.rods[rod].b);
+                self.bodies[j1].force += dir * force * sign / 2.0;
+                self.bodies[j2].force += dir * force * sign / 2.0;
+            }
+        }
+    }
+
+    fn apply_rod_springs(&mut self) {
+        for i in 0..self.rods.len() {
+            let rod = &self.rods[i];
+            let delta = self.bodies[rod.b].pos - self.bodies[rod.a].pos;
+            let len = delta.length();
+            let (a, b, rest) = (rod.a, rod.b, rod.rest);
+            let Some(spring) = self.rods[i].spring.as_mut() else {
+                continue;
+            };
+            let speed = (len - spring.prev_len) / self.config.dt;
+            spring.prev_len = len;
+            if len <= f32::EPSILON {
+                continue;
+            }
+
+            let force = delta / len * (spring.stiffness * (len - rest) + spring.damping * speed);
+            self.bodies[a].force += force;
+            self.bodies[b].force -= force;
+        }
+    }
+
+    fn solve_rods(&mut self) {
+        for rod in self.rods.iter() {
+            let (a, b) = (&self.bodies[rod.a], &self.bodies[rod.b]);
+            let w = a.inv_mass + b.inv_mass;
+            if w <= 0.0 {
+                continue;
+            }
+            let delta = b.pos - a.pos;
+            let len = delta.length();
+            if len <= f32::EPSILON {
+                continue;
+            }
+            let target = len.clamp(rod.min, rod.max);
+            if len == target {
+                continue;
+            }
+            let correction = delta * ((len - target) / (len * w));
+            let (wa, wb) = (a.inv_mass, b.inv_mass);
+            self.bodies[rod.a].pos += correction * wa;
+            self.bodies[rod.b].pos -= correction * wb;
+        }
+    }
+
+    /// Rotates the two outer bodies of each limit around the joint in the plane they span, then moves all three so
+    /// their center of mass stays in place.
+    fn solve_angle_limits(&mut self) {
+        for limit in self.angle_limits.iter() {
+            let (j, a, b) = (&self.bodies[limit.joint], &self.bodies[limit.a], &self.bodies[limit.b]);
+            let w = a.inv_mass + b.inv_mass;
+            let (u, v) = (a.pos - j.pos, b.pos - j.pos);
+            if w <= 0.0 || u.length() <= f32::EPSILON || v.length() <= f32::EPSILON {
+                continue;
+            }
+            let angle = u.angle_between(v);
+            let diff = angle.clamp(limit.min, limit.max) - angle;
+            if diff.abs() <= 1e-6 {
+                continue;
+            }
+
+            // rotating around u x v turns u towards v
+            let axis = u.cross(v).try_normalize().unwrap_or_else(|| u.any_orthonormal_vector());
+            let new_a = j.pos + Quat::from_axis_angle(axis, -diff * a.inv_mass / w) * u;
+            let new_b = j.pos + Quat::from_axis_angle(axis, diff * b.inv_mass / w) * v;
+
+            let moved = [(limit.a, new_a - a.pos), (limit.b, new_b - b.pos)];
+            move_bodies(&mut self.bodies, &moved, &[limit.joint, limit.a, limit.b]);
+        }
+    }
+
+    /// Moves the end of each hinged connector along the hinge axis back to its starting offset, and the joint the
+    /// opposite way according to their masses.
+    fn solve_hinges(&mut self) {
+        for hinge in self.hinges.iter() {
+            let (j, e, o) = (&self.bodies[hinge.joint], &self.bodies[hinge.end], &self.bodies[hinge.other]);
+            let w = j.inv_mass + e.inv_mass;
+            let delta = e.pos - j.pos;
+            let len = delta.length();
+            let Some(other_dir) = (o.pos - j.pos).try_normalize() else {
+                continue;
+            };
+            if w <= 0.0 || len <= f32::EPSILON {
+                continue;
+            }
+
+            let axis = Quat::from_rotation_arc(hinge.other_dir, other_dir) * hinge.axis;
+            let error = delta.dot(axis) / len - hinge.offset;
+            let correction = axis * (error * len / w);
+            let (wj, we) = (j.inv_mass, e.inv_mass);
+            self.bodies[hinge.joint].pos += correction * wj;
+            self.bodies[hinge.end].pos -= correction * we;
+        }
+    }
+
+    /// Pushes bodies below the ground back onto it, adding the distance each was pushed to `normal`.
+    fn solve_ground(&mut self, normal: &mut [f32]) {
+        let ground = self.config.ground_height;
+        for (body, normal) in self.bodies.iter_mut().zip(normal.iter_mut()).filter(|(body, _)| body.inv_mass > 0.0) {
+            let floor = ground + body.radius;
+            let depth = floor - body.pos.y;
+            if depth <= 0.0 {
+                continue;
+            }
+            body.pos.y = floor;
+            body.prev_pos.y = floor;
+            *normal += depth;
+        }
+    }
+
+    /// Coulomb friction, once per step: the sideways motion of each body on the ground is reduced by its friction
+    /// times how far the ground pushed it up during the step.
+    fn apply_friction(&mut self, normal: &[f32]) {
+        for (body, normal) in self.bodies.iter_mut().zip(normal.iter()).filter(|(_, normal)| **normal > 0.0) {
+            let slide = Vec3::new(body.pos.x - body.prev_pos.x, 0.0, body.pos.z - body.prev_pos.z);
+            let max_slide = body.friction * normal;
+            let slide_len = slide.length();
+            if slide_len <= max_slide {
+                body.prev_pos.x = body.pos.x;
+                body.prev_pos.z = body.pos.z;
+            } else {
+                body.prev_pos += slide * (max_slide / slide_len);
+            }
+        }
+    }
+}
+
+/// Applies the given displacements, then shifts every movable body in `group` by the same amount so the center of
+/// mass of the group doesn't move.
+fn move_bodies(bodies: &mut [Body], moved: &[(usize, Vec3)], group: &[usize]) {
+    let mut total_mass = 0.0;
+    let mut shift = Vec3::ZERO;
+    for (body, delta) in moved {
+        let inv_mass = bodies[*body].inv_mass;
+        if inv_mass > 0.0 {
+            shift += *delta / inv_mass;
+        }
+    }
+    for body in group {
+        let inv_mass = bodies[*body].inv_mass;
+        if inv_mass > 0.0 {
+            total_mass += 1.0 / inv_mass;
+        }
+    }
+    for (body, delta) in moved {
+        bodies[*body].pos += *delta;
+    }
+    if total_mass <= 0.0 {
+        return;
+    }
+    for body in group {
+        if bodies[*body].inv_mass > 0.0 {
+            bodies[*body].pos -= shift / total_mass;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::{default_friction, default_radius, Connection, Point};
+
+    /// Joint at `pos` with a default connector to `parent`.
+    fn add_joint(structure: &mut Structure, parent: NodeIndex, pos: Vec3) -> (NodeIndex, EdgeIndex) {
+        let node = structure.add_point(Point { pos, parent: Some(parent), ..default() });
+        let edge = structure.add_connection(parent, node, Connection::default());
+        (node, edge)
+    }
+
+    /// Two legs standing on the ground with a muscle between them.
+    fn walker() -> Structure {
+        let mut structure = Structure::default();
+        let root = structure.add_point(Point { pos: Vec3::new(0.0, 0.0, 0.0), ..default() });
+        let (_, e1) = add_joint(&mut structure, root, Vec3::new(-3.0, -4.5, 0.5));
+        let (_, e2) = add_joint(&mut structure, root, Vec3::new(3.0, -4.5, -0.5));
+        let (_, e3) = add_joint(&mut structure, root, Vec3::new(0.0, -4.5, 3.0));
+        structure.add_muscle_data(e1, e2, MuscleParams::default());
+        structure.add_muscle_data(e2, e3, MuscleParams { phase: 1.0, ..default() });
+        structure
+    }
+
+    fn positions(sim: &Simulation) -> Vec<[u32; 3]> {
+        sim.bodies.iter().map(|body| body.pos.to_array().map(f32::to_bits)).collect()
+    }
+
+    #[test]
+    fn same_input_same_result() {
+        let structure = walker();
+        let mut first = Simulation::new(&structure, SimConfig::default());
+        let mut second = Simulation::new(&structure, SimConfig::default());
+        first.run(5.0);
+        second.run(5.0);
+
+        assert_eq!(positions(&first), positions(&second));
+        // the muscles moved the structure, so the comparison isn't of a structure at rest
+        assert_ne!(positions(&first), positions(&Simulation::new(&structure, SimConfig::default())));
+    }
+
+    #[test]
+    fn joints_rest_on_ground() {
+        let mut structure = Structure::default();
+        structure.add_point(Point { pos: Vec3::new(0.0, 10.0, 0.0), ..default() });
+        let config = SimConfig::default();
+        let mut sim = Simulation::new(&structure, config);
+        sim.run(5.0);
+
+        let body = &sim.bodies[0];
+        assert!((body.pos.y - (config.ground_height + body.radius)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn friction_stops_sliding() {
+        let config = SimConfig::default();
+        let slide = |friction: f32, iterations: usize| {
+            let mut structure = Structure::default();
+            // resting on the ground at zero depth
+            let pos = Vec3::new(0.0, config.ground_height + default_radius(), 0.0);
+            structure.add_point(Point { pos, friction, ..default() });
+            let mut sim = Simulation::new(&structure, SimConfig { iterations, ..config });
+            // moving sideways at 0.1 units per step
+            sim.bodies[0].prev_pos.x -= 0.1;
+            sim.run(2.0);
+            sim.bodies[0].pos.x
+        };
+
+        let frictionless = slide(0.0, config.iterations);
+        let rough = slide(default_friction(), config.iterations);
+        assert!(frictionless > 1.0);
+        assert!(rough < frictionless / 2.0);
+        // friction doesn't depend on how often the constraints are solved
+        assert_eq!(slide(default_friction(), 1), rough);
+        assert_eq!(slide(default_friction(), 4 * config.iterations), rough);
+    }
+}
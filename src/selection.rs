@@ -10,15 +10,19 @@ pub struct SelectionPlugin;
 impl Plugin for SelectionPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<EntitySelected>()
+            .init_resource::<BoxStart>()
             .init_resource::<HighlightMaterials>()
             .add_event::<SelectionUpdateEvent>()
             .add_event::<SelectionBlockEvent>()
 
-            .add_systems(Update, select_on_click)
+            .add_systems(Startup, setup_selection_box)
+            .add_systems(Update, (select_on_click, box_select).chain())
 
             .add_systems(PostUpdate, highlight_selection)
             
-            .add_observer(update_selectables);
+            .add_observer(update_selectables)
+            .add_observer(select_all)
+            .add_observer(invert_selection);
     }
 }
 
@@ -62,7 +66,7 @@ impl FromWorld for HighlightMaterials {
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SelectableEntity {
     Joint(Entity),
     Connector(Entity),
@@ -75,6 +79,14 @@ pub struct Selectable {
     pub selected: bool,
 }
 
+impl SelectableEntity {
+    pub fn entity(&self) -> Entity {
+        match self {
+            SelectableEntity::Joint(e) | SelectableEntity::Connector(e) | SelectableEntity::Muscle(e) => *e,
+        }
+    }
+}
+
 impl Selectable {
     pub fn with_type(entity_type: SelectableEntity) -> Self {
         Selectable {
@@ -84,9 +96,11 @@ impl Selectable {
     }
 }
 
-/// Currently selected Entity. Will be highlighted when SelectionUpdateEvent is triggered.
+/// Currently selected entities. Will be highlighted when SelectionUpdateEvent is triggered. `0` is the active
+/// entity, the one last selected, which operations on a single entity act on. `1` holds every selected entity,
+/// including the active one, in the order they were selected.
 #[derive(Default, Resource)]
-pub struct EntitySelected(pub Option<SelectableEntity>);
+pub struct EntitySelected(pub Option<SelectableEntity>, pub Vec<SelectableEntity>);
 
 impl EntitySelected {
     pub fn is_some(&self) -> bool {
@@ -95,29 +109,55 @@ impl EntitySelected {
     pub fn is_none(&self) -> bool {
         self.0.is_none()
     }
+    /// Replaces the selection with the given entity.
     pub fn set(&mut self, entity: Option<SelectableEntity>) {
-        self.0 = entity
+        self.1 = entity.iter().cloned().collect();
+        self.0 = entity;
     }
-    pub fn contains(&self, entity: Entity) -> bool {
-        if let Some(selected) = &self.0 {
-            return match selected {
-                SelectableEntity::Joint(val) => entity == *val,
-                SelectableEntity::Connector(val) => entity == *val,
-                SelectableEntity::Muscle(val) => entity == *val,
-            }
+    /// Adds the entity to the selection and makes it active.
+    pub fn add(&mut self, entity: SelectableEntity) {
+        if !self.1.contains(&entity) {
+            self.1.push(entity.clone());
         }
-        false
+        self.0 = Some(entity);
     }
-    /// Returns the entity selected if any.
-    pub fn get(&self) -> Option<Entity> {
-        if let Some(selected) = &self.0 {
-            return match selected {
-                SelectableEntity::Joint(val) => Some(*val),
-                SelectableEntity::Connector(val) => Some(*val),
-                SelectableEntity::Muscle(val) => Some(*val),
-            }
+    /// Removes the entity from the selection. The last selected entity left becomes active.
+    pub fn remove(&mut self, entity: Entity) {
+        self.1.retain(|e| e.entity() != entity);
+        if self.0.as_ref().is_some_and(|e| e.entity() == entity) {
+            self.0 = self.1.last().cloned();
+        }
+    }
+    /// Adds the entity to the selection, or removes it if it is already selected.
+    pub fn toggle(&mut self, entity: SelectableEntity) {
+        if self.contains(entity.entity()) {
+            self.remove(entity.entity());
+        } else {
+            self.add(entity);
         }
-        None
+    }
+    /// Whether the entity is part of the selection.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.1.iter().any(|e| e.entity() == entity)
+    }
+    /// Returns the active entity if any.
+    pub fn get(&self) -> Option<Entity> {
+        self.0.as_ref().map(|e| e.entity())
+    }
+    /// Every selected entity.
+    pub fn iter(&self) -> impl Iterator<Item = &SelectableEntity> {
+        self.1.iter()
+    }
+    /// Every selected joint.
+    pub fn joints(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.1.iter().filter_map(|e| match e {
+            SelectableEntity::Joint(joint) => Some(*joint),
+            _ => None,
+        })
+    }
+    /// Number of selected entities.
+    pub fn len(&self) -> usize {
+        self.1.len()
     }
     pub fn is_joint(&self) -> bool {
         matches!(self.0, Some(SelectableEntity::Joint(_)))
@@ -133,13 +173,13 @@ impl EntitySelected {
         // }
         // false
     }
-    pub fn is_muscle(&self) -> bool {
-        matches!(self.0, Some(SelectableEntity::Muscle(_)))
-        // if let Some(SelectableEntity::Muscle(_)) = &self.0 {
-        //     return true
-        // }
-        // false
-    }
+}
+
+/// Deselects everything. Used when the entities of a state are despawned.
+pub fn clear_selection(
+    mut entity_selected: ResMut<EntitySelected>,
+) {
+    entity_selected.set(None);
 }
 
 #[derive(Event)]
@@ -148,6 +188,25 @@ pub struct SelectionUpdateEvent;
 #[derive(Event)]
 pub struct SelectionBlockEvent;
 
+/// Selects every selectable entity, or deselects everything if all of them are already selected.
+#[derive(Event)]
+pub struct SelectAllEvent;
+
+/// Selects every selectable entity that isn't selected and deselects the rest.
+#[derive(Event)]
+pub struct InvertSelectionEvent;
+
+/// Distance in pixels the mouse has to be dragged for a box selection to start.
+const BOX_SELECT_THRESHOLD: f32 = 4.0;
+
+/// Rectangle drawn while box selecting.
+#[derive(Component)]
+struct SelectionBox;
+
+/// Screen position where a box selection may start, set when clicking on nothing.
+#[derive(Resource, Default)]
+struct BoxStart(Option<Vec2>);
+
 fn select_on_click(
     mut commands: Commands,
     mut ray_cast: MeshRayCast,
@@ -156,12 +215,22 @@ fn select_on_click(
     window_q: Query<&Window, With<PrimaryWindow>>,
     mut entity_selected: ResMut<EntitySelected>,
     selectable_q: Query<&Selectable>,
-    mut ev_block: EventReader<SelectionBlockEvent>
+    ui_q: Query<&Interaction>,
+    key_input: Res<ButtonInput<KeyCode>>,
+    mut ev_block: EventReader<SelectionBlockEvent>,
+    mut box_start: ResMut<BoxStart>,
 ) {
+    let shift = key_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
     if !mouse.just_pressed(MouseButton::Left) {
         return
     }
 
+    // Clicks on the UI should not change the selection.
+    if ui_q.iter().any(|interaction| *interaction != Interaction::None) {
+        return
+    }
+
     let (cam, cam_transform) = cam_q.single();
     let Some(mouse_pos) = window_q.single().cursor_position() else {
         return
@@ -169,22 +238,135 @@ fn select_on_click(
     let ray = cam.viewport_to_world(cam_transform, mouse_pos).unwrap();
 
     if let Some((target, _)) = ray_cast.cast_ray(ray, &RayCastSettings::default()).first() {
-        if entity_selected.contains(*target) || ev_block.read().count() > 0 {
+        if ev_block.read().count() > 0 {
             return;
         }
 
         if let Ok(selectable) = selectable_q.get(*target) {
-            entity_selected.set(Some(selectable.entity_type.clone()));
+            if shift {
+                entity_selected.toggle(selectable.entity_type.clone());
+            } else if entity_selected.get() == Some(*target) {
+                return;
+            } else {
+                entity_selected.set(Some(selectable.entity_type.clone()));
+            }
             commands.trigger(SelectionUpdateEvent);
             return;
         }
     }
 
-    // If this is reached, means something other than a Selectable entity has been clicked. In other words, deselect.
-    if entity_selected.is_some() {
+    // If this is reached, means something other than a Selectable entity has been clicked. Either deselect once the
+    // mouse is released or start a box selection if it is dragged.
+    box_start.0 = Some(mouse_pos);
+}
+
+fn setup_selection_box(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            border: UiRect::all(Val::Px(1.0)),
+            display: Display::None,
+            ..default()
+        },
+        BorderColor(Color::srgb(1.0, 0.858, 0.301)),
+        BackgroundColor(Color::srgba(1.0, 0.858, 0.301, 0.1)),
+        SelectionBox,
+    ));
+}
+
+/// System to draw the selection box while the mouse is dragged from nothing, and select every entity inside of it
+/// once released. Releasing without dragging deselects everything. Holding shift adds to the selection instead of
+/// replacing it.
+fn box_select(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    key_input: Res<ButtonInput<KeyCode>>,
+    cam_q: Query<(&Camera, &GlobalTransform)>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    mut entity_selected: ResMut<EntitySelected>,
+    selectable_q: Query<(&Selectable, &GlobalTransform)>,
+    mut box_q: Query<&mut Node, With<SelectionBox>>,
+    mut box_start: ResMut<BoxStart>,
+) {
+    let Ok(mut node) = box_q.get_single_mut() else {
+        return
+    };
+    let (Some(start), Some(cursor)) = (box_start.0, window_q.single().cursor_position()) else {
+        node.display = Display::None;
+        return
+    };
+    let shift = key_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let rect = Rect::from_corners(start, cursor);
+
+    if mouse.pressed(MouseButton::Left) {
+        if start.distance(cursor) > BOX_SELECT_THRESHOLD {
+            node.display = Display::Flex;
+            node.left = Val::Px(rect.min.x);
+            node.top = Val::Px(rect.min.y);
+            node.width = Val::Px(rect.width());
+            node.height = Val::Px(rect.height());
+        }
+        return
+    }
+
+    box_start.0 = None;
+    node.display = Display::None;
+    if start.distance(cursor) <= BOX_SELECT_THRESHOLD {
+        if !shift && entity_selected.is_some() {
+            entity_selected.set(None);
+            commands.trigger(SelectionUpdateEvent);
+        }
+        return
+    }
+
+    let Ok((cam, cam_transform)) = cam_q.get_single() else {
+        return
+    };
+    if !shift {
+        entity_selected.set(None);
+    }
+    for (selectable, transform) in selectable_q.iter() {
+        let Ok(pos) = cam.world_to_viewport(cam_transform, transform.translation()) else {
+            continue
+        };
+        if rect.contains(pos) {
+            entity_selected.add(selectable.entity_type.clone());
+        }
+    }
+    commands.trigger(SelectionUpdateEvent);
+}
+
+/// Selects every selectable entity, or deselects all of them. Triggers with SelectAllEvent.
+fn select_all(
+    _: Trigger<SelectAllEvent>,
+    mut commands: Commands,
+    mut entity_selected: ResMut<EntitySelected>,
+    selectable_q: Query<&Selectable>,
+) {
+    if selectable_q.iter().all(|s| entity_selected.contains(s.entity_type.entity())) {
         entity_selected.set(None);
-        commands.trigger(SelectionUpdateEvent);
+    } else {
+        for selectable in selectable_q.iter() {
+            entity_selected.add(selectable.entity_type.clone());
+        }
+    }
+    commands.trigger(SelectionUpdateEvent);
+}
+
+/// Inverts the selection. Triggers with InvertSelectionEvent.
+fn invert_selection(
+    _: Trigger<InvertSelectionEvent>,
+    mut commands: Commands,
+    mut entity_selected: ResMut<EntitySelected>,
+    selectable_q: Query<&Selectable>,
+) {
+    let previous = std::mem::take(&mut *entity_selected);
+    for selectable in selectable_q.iter() {
+        if !previous.contains(selectable.entity_type.entity()) {
+            entity_selected.add(selectable.entity_type.clone());
+        }
     }
+    commands.trigger(SelectionUpdateEvent);
 }
 
 /// System to update the Selectable components of each entity on selection updates. Triggers with SelectionUpdateEvent
@@ -195,19 +377,23 @@ fn update_selectables(
     mut selectable_query: Query<&mut Selectable>,
     joint_q: Query<&Joint>,
 ) {
-    info!(":: SELECTED: {:?}", entity_selected.0);
+    info!(":: SELECTED: {:?} ({} total)", entity_selected.0, entity_selected.len());
 
     for mut selectable in selectable_query.iter_mut() {
         selectable.selected = false;
     }
 
+    for selected in entity_selected.iter() {
+        if let Ok(mut selectable) = selectable_query.get_mut(selected.entity()) {
+            selectable.selected = true;
+        }
+    }
+
     if !entity_selected.is_some() {
         return;
     }
 
     let entity = entity_selected.get().unwrap();
-    let mut selectable = selectable_query.get_mut(entity).unwrap();
-    selectable.selected = true;
 
     if entity_selected.is_joint() {
         update_parent_edge(entity, &structure, &mut selectable_query, &joint_q, true);
@@ -216,11 +402,15 @@ fn update_selectables(
 
 /// System to change the material of selected entities (via the Selectable component).
 fn highlight_selection(
+    structure: Res<Structure>,
     select_materials: Res<HighlightMaterials>,
     joint_materials: Res<JointMaterial>,
-    mut selectable_q: Query<(&mut MeshMaterial3d<StandardMaterial>, &Selectable), Changed<Selectable>>,
+    mut selectable_q: Query<
+        (&mut MeshMaterial3d<StandardMaterial>, &Selectable, Option<&Connector>),
+        Changed<Selectable>
+    >,
 ) {
-    for (mut material_handle, selectable) in selectable_q.iter_mut() {
+    for (mut material_handle, selectable, connector) in selectable_q.iter_mut() {
         if selectable.selected {
             match selectable.entity_type {
                 SelectableEntity::Joint(_) => *material_handle = MeshMaterial3d(select_materials.joint_color.clone()),
@@ -230,7 +420,13 @@ fn highlight_selection(
         } else {
             match selectable.entity_type {
                 SelectableEntity::Joint(_) => *material_handle = MeshMaterial3d(joint_materials.joint_color.clone()),
-                SelectableEntity::Connector(_) => *material_handle = MeshMaterial3d(joint_materials.connector_color.clone()),
+                SelectableEntity::Connector(_) => {
+                    let joint = connector
+                        .and_then(|c| structure.edge_weight(c.edge_index))
+                        .map(|c| c.joint.clone())
+                        .unwrap_or_default();
+                    *material_handle = MeshMaterial3d(joint_materials.connector_material(&joint));
+                },
                 SelectableEntity::Muscle(_) => *material_handle = MeshMaterial3d(joint_materials.muscle_color.clone())
             }
         }
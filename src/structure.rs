@@ -1,31 +1,231 @@
-use std::ops::{Deref, DerefMut};
+use std::{collections::{BTreeMap, HashSet}, fmt, ops::{Deref, DerefMut}};
 
 use bevy::{prelude::*, utils::HashMap};
 
-use serde::{Serialize, Deserialize};
-use petgraph::{graph::*, stable_graph::StableUnGraph};
+use serde::{Serialize, Deserialize, Deserializer};
+use petgraph::{graph::*, stable_graph::StableUnGraph, visit::EdgeRef};
 
-use crate::{selection::{Selectable, SelectableEntity}, util::{Errors, JointMaterial, JointMeshes}};
+use crate::{selection::{Selectable, SelectableEntity}, util::{joint_scale, Errors, JointMaterial, JointMeshes, JOINT_RADIUS}};
 
-/// Node of body graph. These are joints. `entityid` is the bevy-given EntityID, `pos` is the 3D position vector, and parent is the parent node.
-#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+/// Node of body graph. These are joints. `entityid` is the bevy-given EntityID, `id` identifies the joint in files
+/// and stays the same when other joints are removed or the structure is merged, `pos` is the 3D position vector, and
+/// parent is the parent node. `mass`, `radius` and `friction` are the physical properties of the joint, defaulting to
+/// a unit joint for older files.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Point {
     #[serde(skip)]
     pub entityid: Option<Entity>,
+    #[serde(default)]
+    pub id: u32,
     pub pos: Vec3,
     pub parent: Option<NodeIndex>,
+    #[serde(default = "default_mass")]
+    pub mass: f32,
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+    #[serde(default = "default_friction")]
+    pub friction: f32,
+}
+
+impl Default for Point {
+    fn default() -> Self {
+        Point {
+            entityid: None,
+            id: 0,
+            pos: Vec3::ZERO,
+            parent: None,
+            mass: default_mass(),
+            radius: default_radius(),
+            friction: default_friction(),
+        }
+    }
+}
+
+pub fn default_mass() -> f32 {
+    1.0
+}
+
+pub fn default_radius() -> f32 {
+    JOINT_RADIUS
+}
+
+pub fn default_friction() -> f32 {
+    3.0
 }
 
-/// Edge of structure graph. These are connectors. `entityid` is the bevy-given EntityID, `muscles` maps UNKNOWN
+/// Edge of structure graph. These are connectors. `entityid` is the bevy-given EntityID, `id` identifies the connector
+/// in files like the id of a `Point`, and muscles are identified by the ids of their anchors. `muscles` maps the
+/// opposite anchor of each muscle to the spawned muscle entity, and `muscle_data` maps it to the muscle's parameters.
+/// Both anchors of a muscle hold the same parameters. `joint` and `limits` describe how the connector may move
+/// relative to the other connectors sharing its joints.
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct Connection {
     #[serde(skip)]
     pub entityid: Option<Entity>,
+    #[serde(default)]
+    pub id: u32,
     #[serde(skip)]
     pub muscles: HashMap<EdgeIndex, Entity>, 
-    pub muscle_data: Vec<EdgeIndex>, // use hashmap with value as muscle weight later?
+    #[serde(deserialize_with = "deserialize_muscle_data")]
+    pub muscle_data: BTreeMap<EdgeIndex, MuscleParams>,
+    #[serde(default)]
+    pub joint: JointType,
+    #[serde(default)]
+    pub limits: ConnectionLimits,
+}
+
+/// How a connector may rotate around the joints at its ends, relative to the other connectors sharing them.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub enum JointType {
+    /// Keeps its angle to every other connector sharing one of its joints.
+    Rigid,
+    /// Rotates freely around its joints. Connectors of older files are ball connectors.
+    #[default]
+    Ball,
+    /// Only rotates around `axis`, given in structure space.
+    Hinge { axis: Vec3 },
+    /// Rotates freely like a ball connector, and changes length against a spring pulling it back to its rest length.
+    Spring { stiffness: f32, damping: f32 },
+}
+
+impl JointType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JointType::Rigid => "Rigid",
+            JointType::Ball => "Ball",
+            JointType::Hinge { .. } => "Hinge",
+            JointType::Spring { .. } => "Spring",
+        }
+    }
+}
+
+/// Limits of the movement of a connector. Unset limits don't constrain the connector.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ConnectionLimits {
+    /// Smallest angle in radians to the other connectors sharing a joint.
+    pub min_angle: Option<f32>,
+    /// Largest angle in radians to the other connectors sharing a joint.
+    pub max_angle: Option<f32>,
+    /// Shortest length of a spring connector.
+    pub min_length: Option<f32>,
+    /// Longest length of a spring connector.
+    pub max_length: Option<f32>,
+}
+
+/// Physical parameters of a muscle.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct MuscleParams {
+    /// Maximum force the muscle may exert.
+    pub max_force: f32,
+    /// Length the muscle oscillates around. Uses the distance between the anchors when spawned if not set.
+    pub rest_length: Option<f32>,
+    /// Spring constant of the muscle.
+    pub stiffness: f32,
+    /// Force opposing the change in length of the muscle.
+    pub damping: f32,
+    /// Offset of the activation cycle in radians.
+    pub phase: f32,
+    /// Activation cycles per second.
+    pub frequency: f32,
+    pub label: Option<String>,
+}
+
+impl Default for MuscleParams {
+    fn default() -> Self {
+        MuscleParams {
+            max_force: 100.0,
+            rest_length: None,
+            stiffness: 50.0,
+            damping: 0.0,
+            phase: 0.0,
+            frequency: 0.5,
+            label: None,
+        }
+    }
+}
+
+/// Reads `muscle_data` either as a map of parameters or, for older files, as a bare list of edges which get the
+/// default parameters.
+fn deserialize_muscle_data<'de, D: Deserializer<'de>>(
+    deserializer: D
+) -> Result<BTreeMap<EdgeIndex, MuscleParams>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum MuscleData {
+        Params(BTreeMap<EdgeIndex, MuscleParams>),
+        Edges(Vec<EdgeIndex>),
+    }
+
+    Ok(match MuscleData::deserialize(deserializer)? {
+        MuscleData::Params(params) => params,
+        MuscleData::Edges(edges) => edges.into_iter().map(|e| (e, MuscleParams::default())).collect(),
+    })
+}
+
+
+/// Connectors shorter than this are reported as zero-length.
+const LENGTH_EPSILON: f32 = 1e-4;
+/// Connectors leaving a joint at a smaller angle in radians are reported as overlapping.
+const ANGLE_EPSILON: f32 = 1e-3;
+
+/// Invariant violation found by `Structure::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// The muscle is in the `muscle_data` of the first edge but not of the second.
+    MuscleNotMirrored(EdgeIndex, EdgeIndex),
+    /// The `muscle_data` of the first edge holds a muscle to an edge that doesn't exist.
+    MuscleEdgeMissing(EdgeIndex, EdgeIndex),
+    /// The parent of the joint doesn't exist.
+    ParentMissing(NodeIndex, NodeIndex),
+    /// The parent of the joint isn't connected to it.
+    ParentNotAdjacent(NodeIndex, NodeIndex),
+    /// Following the parents of these joints leads back to the first one.
+    ParentCycle(Vec<NodeIndex>),
+    /// The joints of the connector are at the same position.
+    ZeroLength(EdgeIndex),
+    /// The connectors share a joint and leave it in the same direction.
+    Overlapping(EdgeIndex, EdgeIndex),
+    /// The structure is split into this many parts.
+    Disconnected(usize),
+    /// More than one joint has this id.
+    DuplicateJointId(u32),
+    /// More than one connector has this id.
+    DuplicateConnectorId(u32),
 }
 
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Issue::MuscleNotMirrored(edge, other) =>
+                write!(f, "(MuscleNotMirrored): Muscle {} - {} is missing from connector {}",
+                    edge.index(), other.index(), other.index()),
+            Issue::MuscleEdgeMissing(edge, other) =>
+                write!(f, "(MuscleEdgeMissing): Muscle {} - {} refers to missing connector {}",
+                    edge.index(), other.index(), other.index()),
+            Issue::ParentMissing(node, parent) =>
+                write!(f, "(ParentMissing): Parent {} of joint {} does not exist", parent.index(), node.index()),
+            Issue::ParentNotAdjacent(node, parent) =>
+                write!(f, "(ParentNotAdjacent): Parent {} of joint {} is not connected to it",
+                    parent.index(), node.index()),
+            Issue::ParentCycle(ref nodes) => {
+                let chain: Vec<String> = nodes.iter().chain(nodes.first()).map(|&n| n.index().to_string()).collect();
+                write!(f, "(ParentCycle): Parents of joints {} form a cycle", chain.join(" -> "))
+            },
+            Issue::ZeroLength(edge) =>
+                write!(f, "(ZeroLength): Connector {} has zero length", edge.index()),
+            Issue::Overlapping(edge1, edge2) =>
+                write!(f, "(Overlapping): Connectors {} and {} overlap", edge1.index(), edge2.index()),
+            Issue::Disconnected(components) =>
+                write!(f, "(Disconnected): Structure is split into {} parts", components),
+            Issue::DuplicateJointId(id) =>
+                write!(f, "(DuplicateJointId): More than one joint has id {}", id),
+            Issue::DuplicateConnectorId(id) =>
+                write!(f, "(DuplicateConnectorId): More than one connector has id {}", id),
+        }
+    }
+}
 
 /// Component for each joint entity.
 #[derive(Clone, Debug, Default, Component)]
@@ -39,15 +239,18 @@ pub struct Connector {
     pub edge_index: EdgeIndex,
 }
 
-/// Component to each muscle describing its anchors (edges/connectors).
+/// Component to each muscle describing its anchors (edges/connectors) and parameters.
 #[derive(Component, Default, Debug)]
 pub struct Muscle {
     pub anchor1: Option<EdgeIndex>,
     pub anchor2: Option<EdgeIndex>,
+    pub params: MuscleParams,
 }
 
-/// Graph resource describing the joints (nodes), connections (edges) and muscles (in the edge weights).
+/// Graph resource describing the joints (nodes), connections (edges) and muscles (in the edge weights). Serialized
+/// as the bare graph.
 #[derive(Default, Debug, Clone, Serialize, Deserialize, Resource)]
+#[serde(transparent)]
 pub struct Structure(pub StableUnGraph<Point, Connection>);
 
 impl Deref for Structure {
@@ -65,6 +268,45 @@ impl DerefMut for Structure {
 }
 
 impl Structure {
+    /// New structure with a single root joint at the origin.
+    pub fn with_root() -> Self {
+        let mut structure = Structure::default();
+        structure.add_point(Point::default());
+        structure
+    }
+
+    /// Adds a joint, giving it an id no other joint of the structure has.
+    pub fn add_point(&mut self, point: Point) -> NodeIndex {
+        let id = self.node_weights().map(|p| p.id + 1).max().unwrap_or(0);
+        self.add_node(Point { id, ..point })
+    }
+
+    /// Adds a connector between two joints, giving it an id no other connector of the structure has.
+    pub fn add_connection(&mut self, a: NodeIndex, b: NodeIndex, connection: Connection) -> EdgeIndex {
+        let id = self.edge_weights().map(|c| c.id + 1).max().unwrap_or(0);
+        self.add_edge(a, b, Connection { id, ..connection })
+    }
+
+    /// Numbers the joints and the connectors in index order. Used for structures from files that predate ids.
+    pub fn assign_ids(&mut self) {
+        for (id, point) in self.node_weights_mut().enumerate() {
+            point.id = id as u32;
+        }
+        for (id, connection) in self.edge_weights_mut().enumerate() {
+            connection.id = id as u32;
+        }
+    }
+
+    /// Joint with the given id, if any.
+    pub fn node_by_id(&self, id: u32) -> Option<NodeIndex> {
+        self.node_indices().find(|n| self[*n].id == id)
+    }
+
+    /// Connector with the given id, if any.
+    pub fn edge_by_id(&self, id: u32) -> Option<EdgeIndex> {
+        self.edge_indices().find(|e| self[*e].id == id)
+    }
+
     /// Spawns all the joints, connectors and muscles contained in the structure graph where `#_components` are extra
     /// components that may be desired to be added to the entities when deserializing the graph, and `state` is the game 
     /// state where the object exists.
@@ -96,6 +338,7 @@ impl Structure {
                 &meshes, 
                 &materials, 
                 point_data.pos, 
+                point_data.radius,
                 Some(joint),
                 joint_components.clone(),
                 state
@@ -120,13 +363,15 @@ impl Structure {
                 &materials, 
                 pos1, 
                 pos2, 
+                &edge_data.joint,
                 Some(connector),
                 conn_components.clone(),
                 state
             );
             edge_data.entityid = Some(e);
+            edge_data.muscles.clear();
 
-            for muscle_pair in edge_data.muscle_data.iter() {
+            for (muscle_pair, params) in edge_data.muscle_data.iter() {
                 if let Some(muscle_map) = muscles_complete.get(muscle_pair) {
                     let muscle = muscle_map.get(&edge).unwrap();
                     edge_data.muscles.insert(*muscle_pair, *muscle);
@@ -138,6 +383,7 @@ impl Structure {
                     &materials,
                     Some(edge),
                     Some(*muscle_pair),
+                    params.clone(),
                     muscle_components.clone(),
                     state
                 );
@@ -147,6 +393,300 @@ impl Structure {
         }
     }
 
+    /// Makes `muscle_data` match the muscles spawned on each edge. Parameters of existing muscles are kept and
+    /// muscles without any get the defaults.
+    pub fn sync_muscle_data(&mut self) {
+        for edge in self.edge_weights_mut() {
+            let muscles = &edge.muscles;
+            edge.muscle_data.retain(|e, _| muscles.contains_key(e));
+            for e in muscles.keys() {
+                edge.muscle_data.entry(*e).or_default();
+            }
+        }
+    }
+
+    /// Records a muscle between two edges in the `muscle_data` of both. Returns false if either edge is missing,
+    /// the edges are the same or the muscle already exists.
+    pub fn add_muscle_data(&mut self, edge1: EdgeIndex, edge2: EdgeIndex, params: MuscleParams) -> bool {
+        if edge1 == edge2 || self.edge_weight(edge1).is_none() || self.edge_weight(edge2).is_none() {
+            return false;
+        }
+        if self[edge1].muscle_data.contains_key(&edge2) {
+            return false;
+        }
+        self[edge1].muscle_data.insert(edge2, params.clone());
+        self[edge2].muscle_data.insert(edge1, params);
+        true
+    }
+
+    /// Removes the muscle between two edges from the `muscle_data` of both.
+    pub fn remove_muscle_data(&mut self, edge1: EdgeIndex, edge2: EdgeIndex) {
+        if let Some(weight) = self.edge_weight_mut(edge1) {
+            weight.muscle_data.remove(&edge2);
+        }
+        if let Some(weight) = self.edge_weight_mut(edge2) {
+            weight.muscle_data.remove(&edge1);
+        }
+    }
+
+    /// Moves the muscle between `edge` and `from` to be between `edge` and `to`, keeping its parameters. Returns false
+    /// if there is no such muscle or it can't be added.
+    pub fn move_muscle(&mut self, edge: EdgeIndex, from: EdgeIndex, to: EdgeIndex) -> bool {
+        let Some(params) = self.muscle_params(edge, from).cloned() else {
+            return false;
+        };
+        if from == to || !self.add_muscle_data(edge, to, params) {
+            return false;
+        }
+        self.remove_muscle_data(edge, from);
+        true
+    }
+
+    /// Connectors sharing a joint with `edge` that a muscle from it may be anchored to, sorted. Leaves out the ones
+    /// already holding a muscle to it, except `current`.
+    pub fn anchor_options(&self, edge: EdgeIndex, current: EdgeIndex) -> Vec<EdgeIndex> {
+        let Some((a, b)) = self.edge_endpoints(edge) else {
+            return Vec::new();
+        };
+        let mut options: Vec<EdgeIndex> = self.edges(a).chain(self.edges(b))
+            .map(|e| e.id())
+            .filter(|e| *e != edge && (*e == current || !self[edge].muscle_data.contains_key(e)))
+            .collect();
+        options.sort();
+        options.dedup();
+        options
+    }
+
+    /// Joints connected to `node` that can be its parent without the parents forming a cycle, sorted.
+    pub fn parent_options(&self, node: NodeIndex) -> Vec<NodeIndex> {
+        let mut options: Vec<NodeIndex> = self.neighbors(node)
+            .filter(|neighbor| {
+                // walks at most every joint once, in case the parents already form a cycle
+                let mut ancestor = Some(*neighbor);
+                for _ in 0..self.node_count() {
+                    match ancestor {
+                        Some(n) if n == node => return false,
+                        Some(n) => ancestor = self.node_weight(n).and_then(|p| p.parent),
+                        None => break,
+                    }
+                }
+                true
+            })
+            .collect();
+        options.sort();
+        options.dedup();
+        options
+    }
+
+    /// Parameters of the muscle between two edges, if it exists.
+    pub fn muscle_params(&self, edge1: EdgeIndex, edge2: EdgeIndex) -> Option<&MuscleParams> {
+        self.edge_weight(edge1)?.muscle_data.get(&edge2)
+    }
+
+    /// Sets the parameters of an existing muscle on both of its edges.
+    pub fn set_muscle_params(&mut self, edge1: EdgeIndex, edge2: EdgeIndex, params: MuscleParams) {
+        for (edge, other) in [(edge1, edge2), (edge2, edge1)] {
+            if let Some(data) = self.edge_weight_mut(edge).and_then(|w| w.muscle_data.get_mut(&other)) {
+                *data = params.clone();
+            }
+        }
+    }
+
+    /// Removes a connector along with its muscles, and clears the parent of either endpoint if it was linked through
+    /// this connector. Only updates `muscle_data`, spawned entities are left untouched.
+    pub fn remove_connection(&mut self, edge: EdgeIndex) -> Option<Connection> {
+        let (j1, j2) = self.edge_endpoints(edge)?;
+        let others: Vec<EdgeIndex> = self[edge].muscle_data.keys().copied().collect();
+        for other in others {
+            self.remove_muscle_data(edge, other);
+        }
+        if self[j1].parent == Some(j2) {
+            self[j1].parent = None;
+        }
+        if self[j2].parent == Some(j1) {
+            self[j2].parent = None;
+        }
+        self.remove_edge(edge)
+    }
+
+    /// Removes a joint along with its connectors and their muscles. Only updates `muscle_data`, spawned entities are
+    /// left untouched.
+    pub fn remove_point(&mut self, node: NodeIndex) -> Option<Point> {
+        let edges: Vec<EdgeIndex> = self.edges(node).map(|e| e.id()).collect();
+        for edge in edges {
+            self.remove_connection(edge);
+        }
+        self.remove_node(node)
+    }
+
+    /// Splits a connector in two by inserting a joint at `pos`. Both halves keep the joint type and limits of the
+    /// connector, the half towards its first joint keeping its id too. Muscles move to the half nearer to their other
+    /// anchor, and a joint whose parent was across the connector gets the new joint as parent, which takes the old
+    /// parent. Only updates `muscle_data`, spawned entities are left untouched. Returns the new joint.
+    pub fn subdivide(&mut self, edge: EdgeIndex, pos: Vec3) -> Option<NodeIndex> {
+        let (a, b) = self.edge_endpoints(edge)?;
+        let node = self.add_point(Point {
+            pos,
+            parent: Some(a),
+            ..default()
+        });
+        if self[a].parent == Some(b) {
+            self[a].parent = Some(node);
+            self[node].parent = Some(b);
+        } else if self[b].parent == Some(a) {
+            self[b].parent = Some(node);
+        }
+
+        // the new half gets its id while the old connector still holds its own
+        let old = self[edge].clone();
+        let far = self.add_connection(node, b, Connection {
+            joint: old.joint.clone(),
+            limits: old.limits.clone(),
+            ..default()
+        });
+        for other in old.muscle_data.keys() {
+            self.remove_muscle_data(edge, *other);
+        }
+        self.remove_edge(edge);
+        let near = self.add_edge(a, node, Connection {
+            id: old.id,
+            joint: old.joint,
+            limits: old.limits,
+            ..default()
+        });
+
+        let midpoint = |s: &Self, e: EdgeIndex| {
+            let (n1, n2) = s.edge_endpoints(e).unwrap();
+            (s[n1].pos + s[n2].pos) / 2.0
+        };
+        for (other, params) in old.muscle_data {
+            if self.edge_weight(other).is_none() {
+                continue;
+            }
+            let target = midpoint(self, other);
+            let half = if midpoint(self, near).distance(target) <= midpoint(self, far).distance(target) {
+                near
+            } else {
+                far
+            };
+            self.add_muscle_data(half, other, params);
+        }
+        Some(node)
+    }
+
+    /// Number of connected components of the graph.
+    pub fn component_count(&self) -> usize {
+        let mut visited = std::collections::HashSet::new();
+        let mut count = 0;
+        for node in self.node_indices() {
+            if visited.contains(&node) {
+                continue;
+            }
+            count += 1;
+            let mut dfs = petgraph::visit::Dfs::new(&self.0, node);
+            while let Some(n) = dfs.next(&self.0) {
+                visited.insert(n);
+            }
+        }
+        count
+    }
+
+    /// Checks the invariants of the structure and returns every violation found. An empty list means the structure is
+    /// valid.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+
+        for edge in self.edge_indices() {
+            for other in self[edge].muscle_data.keys() {
+                match self.edge_weight(*other) {
+                    None => issues.push(Issue::MuscleEdgeMissing(edge, *other)),
+                    Some(weight) if !weight.muscle_data.contains_key(&edge) =>
+                        issues.push(Issue::MuscleNotMirrored(edge, *other)),
+                    _ => {},
+                }
+            }
+        }
+
+        for node in self.node_indices() {
+            let Some(parent) = self[node].parent else {
+                continue;
+            };
+            if !self.contains_node(parent) {
+                issues.push(Issue::ParentMissing(node, parent));
+            } else if self.find_edge(node, parent).is_none() {
+                issues.push(Issue::ParentNotAdjacent(node, parent));
+            }
+        }
+
+        // follows the parents of every joint, the joints of a walked chain are known not to lead into a new cycle
+        let mut walked = HashSet::new();
+        for start in self.node_indices() {
+            let mut chain = Vec::new();
+            let mut node = start;
+            while !walked.contains(&node) {
+                if let Some(i) = chain.iter().position(|n| *n == node) {
+                    issues.push(Issue::ParentCycle(chain[i..].to_vec()));
+                    break;
+                }
+                chain.push(node);
+                match self[node].parent.filter(|p| self.contains_node(*p)) {
+                    Some(parent) => node = parent,
+                    None => break,
+                }
+            }
+            walked.extend(chain);
+        }
+
+        for edge in self.edge_indices() {
+            let (n1, n2) = self.edge_endpoints(edge).unwrap();
+            if self[n1].pos.distance(self[n2].pos) < LENGTH_EPSILON {
+                issues.push(Issue::ZeroLength(edge));
+            }
+        }
+
+        // connectors leaving a joint in the same direction lie on top of each other
+        let mut overlapping = HashSet::new();
+        for node in self.node_indices() {
+            let directions: Vec<(EdgeIndex, Vec3)> = self.edges(node)
+                .filter_map(|e| {
+                    let other = if e.source() == node { e.target() } else { e.source() };
+                    let offset = self[other].pos - self[node].pos;
+                    (offset.length() >= LENGTH_EPSILON).then(|| (e.id(), offset.normalize()))
+                })
+                .collect();
+            for (i, (edge1, dir1)) in directions.iter().enumerate() {
+                for (edge2, dir2) in directions[i + 1..].iter() {
+                    let pair = (*edge1.min(edge2), *edge1.max(edge2));
+                    if dir1.angle_between(*dir2) < ANGLE_EPSILON && overlapping.insert(pair) {
+                        issues.push(Issue::Overlapping(pair.0, pair.1));
+                    }
+                }
+            }
+        }
+
+        let components = self.component_count();
+        if components > 1 {
+            issues.push(Issue::Disconnected(components));
+        }
+
+        // files refer to the elements by id, so the ids have to stay unique
+        let mut seen = HashSet::new();
+        let mut duplicates = HashSet::new();
+        for point in self.node_weights() {
+            if !seen.insert(point.id) && duplicates.insert(point.id) {
+                issues.push(Issue::DuplicateJointId(point.id));
+            }
+        }
+        seen.clear();
+        duplicates.clear();
+        for connection in self.edge_weights() {
+            if !seen.insert(connection.id) && duplicates.insert(connection.id) {
+                issues.push(Issue::DuplicateConnectorId(connection.id));
+            }
+        }
+        issues
+    }
+
     /// Converts a given node index to its respective entity id if it exists.
     pub fn node_to_entity(&self, node: NodeIndex) -> Option<Entity> {
         let Some(pdata) = self.node_weight(node) else {
@@ -180,7 +720,7 @@ impl Structure {
     }
 }
 
-/// Creates a joint with the given position, joint data and edit mode. The Joint component should
+/// Creates a joint with the given position, radius, joint data and edit mode. The Joint component should
 /// be manually assigned later if no joint data is passed. `components` are extra components to be added if desired,
 /// and `state` is the game state where the object exists.
 pub fn create_joint<J: Bundle, S: Bundle>(
@@ -188,6 +728,7 @@ pub fn create_joint<J: Bundle, S: Bundle>(
     meshes: &Res<JointMeshes>,
     materials: &Res<JointMaterial>,
     pos: Vec3,
+    radius: f32,
     joint_data: Option<Joint>,
     components: J,
     state: S,
@@ -196,7 +737,7 @@ pub fn create_joint<J: Bundle, S: Bundle>(
         (
             Mesh3d(meshes.head.clone()),
             MeshMaterial3d(materials.joint_color.clone()),
-            Transform::from_translation(pos),
+            Transform::from_translation(pos).with_scale(joint_scale(radius)),
             components,
             state,
         )
@@ -209,14 +750,16 @@ pub fn create_joint<J: Bundle, S: Bundle>(
     e
 }
 
-/// Creates a connector between the given joint positions with the connector data. The Connector component should
-/// be manually assigned later if no connector data is passed. `state` is the game state where the object exists.
+/// Creates a connector of the given joint type between the given joint positions with the connector data. The
+/// Connector component should be manually assigned later if no connector data is passed. `state` is the game state
+/// where the object exists.
 pub fn create_connector<C: Bundle, S: Bundle>(
     commands: &mut Commands,
     meshes: &Res<JointMeshes>,
     materials: &Res<JointMaterial>,
     joint1_pos: Vec3,
     joint2_pos: Vec3,
+    joint: &JointType,
     connector_data: Option<Connector>,
     components: C,
     state: S,
@@ -234,7 +777,7 @@ pub fn create_connector<C: Bundle, S: Bundle>(
 
     let e = commands.spawn((
         Mesh3d(meshes.connector.clone()),
-        MeshMaterial3d(materials.connector_color.clone()),
+        MeshMaterial3d(materials.connector_material(joint)),
         Transform::from_matrix(rotate * position),
         components,
         state,
@@ -249,7 +792,7 @@ pub fn create_connector<C: Bundle, S: Bundle>(
     e
 }
 
-/// Creates a muscle with the given anchors. `components` are extra components to be added if needed, 
+/// Creates a muscle with the given anchors and parameters. `components` are extra components to be added if needed, 
 /// `state` is the game state where the object exists.
 pub fn create_muscle<M: Bundle, S: Bundle> (
     commands: &mut Commands,
@@ -257,13 +800,14 @@ pub fn create_muscle<M: Bundle, S: Bundle> (
     materials: &Res<JointMaterial>,
     anchor1: Option<EdgeIndex>,
     anchor2: Option<EdgeIndex>,
+    params: MuscleParams,
     components: M,
     state: S,
 ) -> Entity{
     let e = commands.spawn((
         Mesh3d(meshes.connector.clone()),
         MeshMaterial3d(materials.muscle_color.clone()),
-        Muscle { anchor1, anchor2 },
+        Muscle { anchor1, anchor2, params },
         components,
         state,
         
@@ -271,4 +815,204 @@ pub fn create_muscle<M: Bundle, S: Bundle> (
     commands.entity(e).insert(Selectable::with_type(SelectableEntity::Muscle(e)));
     info!(":: Created Muscle: {:?}", e);
     e
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Root joint with a connector along x and one along y, which has no issues.
+    fn fan() -> (Structure, [NodeIndex; 3], [EdgeIndex; 2]) {
+        let mut structure = Structure::default();
+        let root = structure.add_point(Point::default());
+        let n1 = structure.add_point(Point { pos: Vec3::X, parent: Some(root), ..default() });
+        let n2 = structure.add_point(Point { pos: Vec3::Y, parent: Some(root), ..default() });
+        let e1 = structure.add_connection(root, n1, Connection::default());
+        let e2 = structure.add_connection(root, n2, Connection::default());
+        (structure, [root, n1, n2], [e1, e2])
+    }
+
+    #[test]
+    fn valid_structure_has_no_issues() {
+        let (mut structure, _, [e1, e2]) = fan();
+        structure.add_muscle_data(e1, e2, MuscleParams::default());
+        assert_eq!(structure.validate(), Vec::new());
+    }
+
+    #[test]
+    fn muscle_not_mirrored() {
+        let (mut structure, _, [e1, e2]) = fan();
+        structure[e1].muscle_data.insert(e2, MuscleParams::default());
+        assert_eq!(structure.validate(), vec![Issue::MuscleNotMirrored(e1, e2)]);
+    }
+
+    #[test]
+    fn muscle_edge_missing() {
+        let (mut structure, _, [e1, _]) = fan();
+        let missing = EdgeIndex::new(7);
+        structure[e1].muscle_data.insert(missing, MuscleParams::default());
+        assert_eq!(structure.validate(), vec![Issue::MuscleEdgeMissing(e1, missing)]);
+    }
+
+    #[test]
+    fn parent_missing() {
+        let (mut structure, [_, n1, _], _) = fan();
+        let missing = NodeIndex::new(9);
+        structure[n1].parent = Some(missing);
+        assert_eq!(structure.validate(), vec![Issue::ParentMissing(n1, missing)]);
+    }
+
+    #[test]
+    fn parent_not_adjacent() {
+        let (mut structure, [_, n1, n2], _) = fan();
+        structure[n2].parent = Some(n1);
+        assert_eq!(structure.validate(), vec![Issue::ParentNotAdjacent(n2, n1)]);
+    }
+
+    #[test]
+    fn parent_cycle() {
+        let (mut structure, [root, n1, _], _) = fan();
+        structure[root].parent = Some(n1);
+        assert_eq!(structure.validate(), vec![Issue::ParentCycle(vec![root, n1])]);
+    }
+
+    #[test]
+    fn zero_length() {
+        let (mut structure, [_, n1, _], [e1, _]) = fan();
+        structure[n1].pos = Vec3::ZERO;
+        assert_eq!(structure.validate(), vec![Issue::ZeroLength(e1)]);
+    }
+
+    #[test]
+    fn overlapping() {
+        let (mut structure, [_, _, n2], [e1, e2]) = fan();
+        structure[n2].pos = Vec3::X * 2.0;
+        assert_eq!(structure.validate(), vec![Issue::Overlapping(e1, e2)]);
+    }
+
+    #[test]
+    fn disconnected() {
+        let (mut structure, _, _) = fan();
+        structure.add_point(Point { pos: Vec3::Z, ..default() });
+        assert_eq!(structure.validate(), vec![Issue::Disconnected(2)]);
+    }
+
+    #[test]
+    fn parent_options_leave_out_descendants() {
+        let (mut structure, [root, n1, n2], _) = fan();
+        let n3 = structure.add_point(Point { pos: Vec3::X * 2.0, parent: Some(n1), ..default() });
+        structure.add_connection(n1, n3, Connection::default());
+        structure.add_connection(n3, n2, Connection::default());
+
+        assert_eq!(structure.parent_options(n1), vec![root]);
+        assert_eq!(structure.parent_options(root), Vec::new());
+        assert_eq!(structure.parent_options(n2), vec![root, n3]);
+    }
+
+    #[test]
+    fn move_muscle_keeps_params() {
+        let (mut structure, [_, n1, _], [e1, e2]) = fan();
+        let n3 = structure.add_point(Point { pos: Vec3::new(1.0, 1.0, 0.0), parent: Some(n1), ..default() });
+        let e3 = structure.add_connection(n1, n3, Connection::default());
+        let params = MuscleParams { phase: 1.0, ..default() };
+        structure.add_muscle_data(e1, e2, params.clone());
+
+        assert_eq!(structure.anchor_options(e1, e2), vec![e2, e3]);
+        assert!(structure.move_muscle(e1, e2, e3));
+        assert_eq!(structure.muscle_params(e1, e3), Some(&params));
+        assert_eq!(structure.muscle_params(e3, e1), Some(&params));
+        assert_eq!(structure.muscle_params(e1, e2), None);
+        assert_eq!(structure.muscle_params(e2, e1), None);
+        assert_eq!(structure.validate(), Vec::new());
+    }
+
+    /// Connector from `a` to `b` along x, with a connector up from either end and a muscle from each of them to it.
+    fn ladder() -> (Structure, [NodeIndex; 4], [EdgeIndex; 3]) {
+        let mut structure = Structure::default();
+        let a = structure.add_point(Point::default());
+        let b = structure.add_point(Point { pos: Vec3::X * 4.0, ..default() });
+        let c = structure.add_point(Point { pos: Vec3::Y * 4.0, parent: Some(a), ..default() });
+        let d = structure.add_point(Point { pos: Vec3::new(4.0, 4.0, 0.0), parent: Some(b), ..default() });
+        let ab = structure.add_connection(a, b, Connection { joint: JointType::Rigid, ..default() });
+        let ac = structure.add_connection(a, c, Connection::default());
+        let bd = structure.add_connection(b, d, Connection::default());
+        structure.add_muscle_data(ab, ac, MuscleParams { phase: 1.0, ..default() });
+        structure.add_muscle_data(ab, bd, MuscleParams { phase: 2.0, ..default() });
+        (structure, [a, b, c, d], [ab, ac, bd])
+    }
+
+    /// Subdivides the middle of the ladder connector, checking what doesn't depend on the parents. Returns the new
+    /// joint.
+    fn subdivide_ladder(structure: &mut Structure, [a, b]: [NodeIndex; 2], [ab, ac, bd]: [EdgeIndex; 3]) -> NodeIndex {
+        let old_id = structure[ab].id;
+        let next_joint_id = structure.node_weights().map(|p| p.id).max().unwrap() + 1;
+        let next_connector_id = structure.edge_weights().map(|c| c.id).max().unwrap() + 1;
+
+        let node = structure.subdivide(ab, Vec3::X * 2.0).unwrap();
+        assert_eq!(structure.validate(), Vec::new());
+        assert_eq!(structure[node].pos, Vec3::X * 2.0);
+
+        // the near half keeps the id, the new joint and the far half get fresh ones
+        let near = structure.find_edge(a, node).unwrap();
+        let far = structure.find_edge(node, b).unwrap();
+        assert!(structure.find_edge(a, b).is_none());
+        assert_eq!(structure[near].id, old_id);
+        assert_eq!(structure[node].id, next_joint_id);
+        assert_eq!(structure[far].id, next_connector_id);
+        assert_eq!(structure[near].joint, JointType::Rigid);
+        assert_eq!(structure[far].joint, JointType::Rigid);
+
+        // each muscle moves to the half next to its other anchor, held by both anchors
+        for (half, other, phase) in [(near, ac, 1.0), (far, bd, 2.0)] {
+            assert_eq!(structure[half].muscle_data.len(), 1);
+            assert_eq!(structure.muscle_params(half, other).map(|p| p.phase), Some(phase));
+            assert_eq!(structure.muscle_params(other, half).map(|p| p.phase), Some(phase));
+            assert_eq!(structure[other].muscle_data.len(), 1);
+        }
+        node
+    }
+
+    #[test]
+    fn subdivide_child_across() {
+        let (mut structure, [a, b, _, _], edges) = ladder();
+        structure[b].parent = Some(a);
+        let node = subdivide_ladder(&mut structure, [a, b], edges);
+        assert_eq!(structure[a].parent, None);
+        assert_eq!(structure[node].parent, Some(a));
+        assert_eq!(structure[b].parent, Some(node));
+    }
+
+    #[test]
+    fn subdivide_parent_across() {
+        let (mut structure, [a, b, _, _], edges) = ladder();
+        structure[a].parent = Some(b);
+        let node = subdivide_ladder(&mut structure, [a, b], edges);
+        assert_eq!(structure[a].parent, Some(node));
+        assert_eq!(structure[node].parent, Some(b));
+        assert_eq!(structure[b].parent, None);
+    }
+
+    #[test]
+    fn subdivide_unrelated_link() {
+        let (mut structure, [a, b, _, d], edges) = ladder();
+        structure[d].parent = None;
+        structure[b].parent = Some(d);
+        let node = subdivide_ladder(&mut structure, [a, b], edges);
+        assert_eq!(structure[a].parent, None);
+        assert_eq!(structure[b].parent, Some(d));
+        assert_eq!(structure[node].parent, Some(a));
+    }
+
+    #[test]
+    fn duplicate_joint_id() {
+        let (mut structure, [_, n1, n2], _) = fan();
+        structure[n2].id = structure[n1].id;
+        assert_eq!(structure.validate(), vec![Issue::DuplicateJointId(structure[n1].id)]);
+    }
+
+    #[test]
+    fn duplicate_connector_id() {
+        let (mut structure, _, [e1, e2]) = fan();
+        structure[e2].id = structure[e1].id;
+        assert_eq!(structure.validate(), vec![Issue::DuplicateConnectorId(structure[e1].id)]);
+    }
+}
@@ -2,6 +2,8 @@ use core::fmt;
 use bevy::prelude::*;
 use petgraph::stable_graph::{NodeIndex, EdgeIndex};
 
+use crate::structure::JointType;
+
 
 pub const JOINT_RADIUS: f32 = 1.0;
 
@@ -35,10 +37,26 @@ impl fmt::Display for Errors {
 #[derive(Resource)]
 pub struct JointMaterial {
     pub joint_color: Handle<StandardMaterial>,
+    /// Color of ball connectors.
     pub connector_color: Handle<StandardMaterial>,
+    pub rigid_color: Handle<StandardMaterial>,
+    pub hinge_color: Handle<StandardMaterial>,
+    pub spring_color: Handle<StandardMaterial>,
     pub muscle_color: Handle<StandardMaterial>,
 }
 
+impl JointMaterial {
+    /// Material of a connector with the given joint type.
+    pub fn connector_material(&self, joint: &JointType) -> Handle<StandardMaterial> {
+        match joint {
+            JointType::Rigid => self.rigid_color.clone(),
+            JointType::Ball => self.connector_color.clone(),
+            JointType::Hinge { .. } => self.hinge_color.clone(),
+            JointType::Spring { .. } => self.spring_color.clone(),
+        }
+    }
+}
+
 impl FromWorld for JointMaterial {
     fn from_world(world: &mut World) -> Self {
         let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
@@ -51,6 +69,27 @@ impl FromWorld for JointMaterial {
                     ..default()
                 }
             ),
+            rigid_color: materials.add(
+                StandardMaterial {
+                    base_color: Color::srgb(0.4, 0.4, 0.4,),
+                    unlit: true,
+                    ..default()
+                }
+            ),
+            hinge_color: materials.add(
+                StandardMaterial {
+                    base_color: Color::srgb(0.3, 0.5, 0.9,),
+                    unlit: true,
+                    ..default()
+                }
+            ),
+            spring_color: materials.add(
+                StandardMaterial {
+                    base_color: Color::srgb(0.4, 0.8, 0.4,),
+                    unlit: true,
+                    ..default()
+                }
+            ),
             muscle_color: materials.add(StandardMaterial {
                 base_color: Color::srgb(1.0, 0.0, 0.0),
                 unlit: true,
@@ -86,6 +125,11 @@ impl FromWorld for JointMeshes {
     }
 }
 
+/// Scale of a joint mesh with the given radius.
+pub fn joint_scale(radius: f32) -> Vec3 {
+    Vec3::splat(radius / JOINT_RADIUS)
+}
+
 /// Despawn all entities and their children with a given component type
 pub fn despawn_all<T: Component>(mut commands: Commands, q: Query<Entity, With<T>>) {
     for e in q.iter() {
@@ -98,13 +142,6 @@ pub fn get_intersect_plane_ray(plane_pos: Vec3, plane_normal: Vec3, ray: Ray3d)
     ray.origin + ((plane_pos - ray.origin).dot(plane_normal))/(ray.direction.dot(plane_normal)) * Vec3::from(ray.direction)
 }
 
-/// Returns the quaternion needed for `src` to rotate around the `axis` to reach `dest`.
-/// 
-/// `src` and `dest` has to be orthogonal to the `axis`.
-pub fn get_axis_rotation(src: Vec3, dest: Vec3, axis: Vec3) -> Quat {
-    Quat::from_axis_angle(axis, get_rotation_angle(src, dest, axis))
-}
-
 /// Returns the angle needed for `src` to rotate around the `axis` to reach `dest`.
 /// 
 /// `src` and `dest` has to be orthogonal to the `axis`.